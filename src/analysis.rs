@@ -0,0 +1,416 @@
+use core::ops::Range;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use disasm::disassemble;
+
+/// What a byte of the program is used for, as far as following the code from the start can tell
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteKind {
+    /// Never reached or read, often data that's only used through computed addresses
+    Unknown,
+    Code,
+    /// Drawn with DRW
+    Sprite,
+    /// Read or written by Fx33, Fx55 or Fx65
+    Data
+}
+
+/// How control leaves a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Exit {
+    /// On to the next instruction
+    Next(u16),
+    Jump(u16),
+    /// Where a skip instruction goes when it skips, `Next` being where it goes when it doesn't
+    Skip(u16),
+    /// Calls a subroutine, which comes back to the `Next` exit
+    Call(u16),
+    Return,
+    /// Bnnn, which jumps to nnn plus V0
+    Computed(u16),
+    /// 00FD, the SUPER-CHIP instruction that stops the interpreter
+    Halt
+}
+
+/// Instructions that always run one after the other
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub start: u16,
+    /// Address of each instruction
+    pub instructions: Vec<u16>,
+    pub exits: Vec<Exit>
+}
+
+/// What was found by following every jump, call and skip from the start of a program
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub start: u16,
+    pub bytes: Vec<u8>,
+    /// Kind of each byte in `bytes`
+    pub kinds: Vec<ByteKind>,
+    /// Address of every instruction reached
+    pub instructions: BTreeSet<u16>,
+    /// By start address
+    pub blocks: BTreeMap<u16, Block>,
+    /// Addresses that are called
+    pub subroutines: BTreeSet<u16>,
+    /// Bnnn instructions, where the analysis can't tell where they go
+    pub computed_jumps: BTreeSet<u16>,
+    /// Fx33 and Fx55 instructions that write over code
+    pub self_modifying: BTreeSet<u16>
+}
+
+fn instruction_at(bytes: &[u8], index: usize) -> u16 {
+    (bytes[index] as u16) << 8 | bytes[index + 1] as u16
+}
+
+/// Where control can go after the instruction at `addr`
+fn exits(addr: u16, instruction: u16) -> Vec<Exit> {
+    let nnn = instruction & 0xFFF;
+    match instruction >> 12 {
+        0x0 if instruction == 0x00EE => vec![Exit::Return],
+        0x0 if instruction == 0x00FD => vec![Exit::Halt],
+        0x1 => vec![Exit::Jump(nnn)],
+        0x2 => vec![Exit::Call(nnn), Exit::Next(addr + 2)],
+        0x3 | 0x4 => vec![Exit::Next(addr + 2), Exit::Skip(addr + 4)],
+        0x5 | 0x9 if instruction & 0xF == 0 => vec![Exit::Next(addr + 2), Exit::Skip(addr + 4)],
+        0xB => vec![Exit::Computed(nnn)],
+        0xE if instruction & 0xFF == 0x9E || instruction & 0xFF == 0xA1 => {
+            vec![Exit::Next(addr + 2), Exit::Skip(addr + 4)]
+        },
+        _ => vec![Exit::Next(addr + 2)]
+    }
+}
+
+/// True if the instruction at `addr` is the last one in its block
+fn ends_block(addr: u16, instruction: u16) -> bool {
+    exits(addr, instruction) != [Exit::Next(addr + 2)]
+}
+
+impl Analysis {
+    /// Follow the code of a program loaded at `start`, keeping track of I where it's set with Annn
+    /// so that sprites and data can be told apart from code
+    pub fn new(bytes: &[u8], start: u16) -> Analysis {
+        let mut analysis = Analysis {
+            start, bytes: bytes.to_vec(), kinds: vec![ByteKind::Unknown; bytes.len()],
+            instructions: BTreeSet::new(), blocks: BTreeMap::new(), subroutines: BTreeSet::new(),
+            computed_jumps: BTreeSet::new(), self_modifying: BTreeSet::new()
+        };
+
+        // Each address is followed once for every value I is known to have there
+        let mut work = vec![(start, None)];
+        let mut seen = BTreeSet::new();
+        let mut writes = Vec::new();
+        while let Some((addr, i)) = work.pop() {
+            let index = match analysis.index(addr) {
+                Some(index) if index + 1 < bytes.len() => index,
+                _ => continue
+            };
+            if !seen.insert((addr, i)) {
+                continue;
+            }
+
+            analysis.instructions.insert(addr);
+            analysis.kinds[index] = ByteKind::Code;
+            analysis.kinds[index + 1] = ByteKind::Code;
+
+            let instruction = instruction_at(bytes, index);
+            let x = (instruction >> 8) & 0xF;
+            let mut i_after = i;
+            match instruction {
+                a if a & 0xF000 == 0xA000 => i_after = Some(a & 0xFFF),
+                a if a & 0xF000 == 0xD000 => if let Some(i) = i {
+                    // Dxy0 draws a 16x16 sprite on SUPER-CHIP
+                    let rows = match a & 0xF { 0 => 32, n => n };
+                    analysis.mark(i..i + rows, ByteKind::Sprite);
+                },
+                a if a & 0xF0FF == 0xF033 || a & 0xF0FF == 0xF055 => {
+                    if let Some(i) = i {
+                        let end = if a & 0xFF == 0x33 { i + 3 } else { i + x + 1 };
+                        analysis.mark(i..end, ByteKind::Data);
+                        writes.push((addr, i..end));
+                    }
+                    // Fx55 moves I along with some quirks
+                    i_after = if a & 0xFF == 0x55 { None } else { i };
+                },
+                a if a & 0xF0FF == 0xF065 => {
+                    if let Some(i) = i {
+                        analysis.mark(i..i + x + 1, ByteKind::Data);
+                    }
+                    i_after = None;
+                },
+                a if a & 0xF0FF == 0xF01E || a & 0xF0FF == 0xF029 || a & 0xF0FF == 0xF030 => i_after = None,
+                _ => {}
+            }
+
+            for exit in exits(addr, instruction) {
+                match exit {
+                    // A subroutine might change I
+                    Exit::Next(next) if instruction & 0xF000 == 0x2000 => work.push((next, None)),
+                    Exit::Next(next) | Exit::Jump(next) | Exit::Skip(next) => work.push((next, i_after)),
+                    Exit::Call(target) => {
+                        analysis.subroutines.insert(target);
+                        work.push((target, i_after));
+                    },
+                    Exit::Computed(_) => { analysis.computed_jumps.insert(addr); },
+                    Exit::Return | Exit::Halt => {}
+                }
+            }
+        }
+
+        for (addr, range) in writes {
+            if range.clone().any(|a| analysis.kind(a) == ByteKind::Code) {
+                analysis.self_modifying.insert(addr);
+            }
+        }
+        analysis.find_blocks();
+        analysis
+    }
+
+    fn index(&self, addr: u16) -> Option<usize> {
+        let index = (addr as usize).checked_sub(self.start as usize)?;
+        if index < self.bytes.len() { Some(index) } else { None }
+    }
+
+    /// Marks the bytes in `range` that aren't code
+    fn mark(&mut self, range: Range<u16>, kind: ByteKind) {
+        for addr in range {
+            if let Some(index) = self.index(addr) {
+                if self.kinds[index] != ByteKind::Code {
+                    self.kinds[index] = kind;
+                }
+            }
+        }
+    }
+
+    /// The kind of the byte at `addr`, `Unknown` outside the program
+    pub fn kind(&self, addr: u16) -> ByteKind {
+        self.index(addr).map_or(ByteKind::Unknown, |index| self.kinds[index])
+    }
+
+    fn instruction(&self, addr: u16) -> u16 {
+        instruction_at(&self.bytes, self.index(addr).unwrap())
+    }
+
+    fn find_blocks(&mut self) {
+        let mut leaders: BTreeSet<u16> = self.subroutines.clone();
+        leaders.insert(self.start);
+        for &addr in &self.instructions {
+            let instruction = self.instruction(addr);
+            if ends_block(addr, instruction) {
+                leaders.extend(exits(addr, instruction).into_iter().filter_map(|exit| match exit {
+                    Exit::Next(a) | Exit::Jump(a) | Exit::Skip(a) | Exit::Call(a) => Some(a),
+                    _ => None
+                }));
+            }
+        }
+
+        for &leader in &leaders {
+            if !self.instructions.contains(&leader) {
+                continue;
+            }
+            let mut block = Block { start: leader, instructions: Vec::new(), exits: Vec::new() };
+            let mut addr = leader;
+            loop {
+                block.instructions.push(addr);
+                let instruction = self.instruction(addr);
+                if ends_block(addr, instruction) {
+                    block.exits = exits(addr, instruction);
+                    break;
+                }
+                let next = addr + 2;
+                if leaders.contains(&next) || !self.instructions.contains(&next) {
+                    block.exits = vec![Exit::Next(next)];
+                    break;
+                }
+                addr = next;
+            }
+            self.blocks.insert(leader, block);
+        }
+    }
+
+    /// The subroutines each subroutine calls, with the start of the program as the first "subroutine"
+    pub fn call_graph(&self) -> BTreeMap<u16, BTreeSet<u16>> {
+        let mut graph = BTreeMap::new();
+        for &entry in Some(&self.start).into_iter().chain(self.subroutines.iter()) {
+            let mut callees = BTreeSet::new();
+            let mut seen = BTreeSet::new();
+            let mut work = vec![entry];
+            while let Some(addr) = work.pop() {
+                let block = match self.blocks.get(&addr) {
+                    Some(block) if seen.insert(addr) => block,
+                    _ => continue
+                };
+                for exit in &block.exits {
+                    match *exit {
+                        Exit::Next(a) | Exit::Jump(a) | Exit::Skip(a) => work.push(a),
+                        Exit::Call(a) => { callees.insert(a); },
+                        _ => {}
+                    }
+                }
+            }
+            graph.insert(entry, callees);
+        }
+        graph
+    }
+
+    /// Runs of bytes of one kind, as address ranges
+    pub fn ranges(&self, kind: ByteKind) -> Vec<Range<u16>> {
+        let mut ranges: Vec<Range<u16>> = Vec::new();
+        for (index, _) in self.kinds.iter().enumerate().filter(|&(_, &k)| k == kind) {
+            let addr = self.start + index as u16;
+            match ranges.last_mut() {
+                Some(range) if range.end == addr => range.end += 1,
+                _ => ranges.push(addr..addr + 1)
+            }
+        }
+        ranges
+    }
+
+    /// Like `disasm::disassemble_program`, but with sprites and data as `DB` bytes.
+    /// Bytes that weren't reached are still disassembled in case they're code the analysis missed.
+    pub fn listing(&self) -> Vec<(u16, u16, String)> {
+        let mut listing = Vec::new();
+        let mut index = 0;
+        while index < self.bytes.len() {
+            let addr = self.start + index as u16;
+            let byte = self.bytes[index];
+            let whole_instruction = index + 1 < self.bytes.len() && match self.kinds[index] {
+                ByteKind::Code => self.instructions.contains(&addr),
+                ByteKind::Unknown => self.kinds[index + 1] == ByteKind::Unknown,
+                _ => false
+            };
+            if whole_instruction {
+                let instruction = instruction_at(&self.bytes, index);
+                listing.push((addr, instruction, disassemble(instruction)));
+                index += 2;
+            } else if self.kinds[index] == ByteKind::Sprite {
+                let row: String = (0..8).map(|bit| if (byte << bit) & 0x80 != 0 { '#' } else { '.' }).collect();
+                listing.push((addr, byte as u16, format!("DB 0x{:02X} ; {}", byte, row)));
+                index += 1;
+            } else {
+                listing.push((addr, byte as u16, format!("DB 0x{:02X}", byte)));
+                index += 1;
+            }
+        }
+        listing
+    }
+
+    /// The control flow graph in Graphviz format. Subroutines have a double border, blocks ending
+    /// in a computed jump are red and blocks that write over code are orange.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph program {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let label: String = block.instructions.iter()
+                .map(|&addr| format!("{:03X}: {}\\l", addr, disassemble(self.instruction(addr))))
+                .collect();
+            let mut attributes = format!("label=\"{}\"", label);
+            if block.start == self.start || self.subroutines.contains(&block.start) {
+                attributes += ", peripheries=2";
+            }
+            if block.instructions.iter().any(|a| self.computed_jumps.contains(a)) {
+                attributes += ", color=red";
+            } else if block.instructions.iter().any(|a| self.self_modifying.contains(a)) {
+                attributes += ", color=orange";
+            }
+            dot += &format!("    b{:03X} [{}];\n", block.start, attributes);
+
+            for exit in &block.exits {
+                let (target, attributes) = match *exit {
+                    Exit::Next(a) | Exit::Jump(a) => (a, ""),
+                    Exit::Skip(a) => (a, " [label=\"skip\"]"),
+                    Exit::Call(a) => (a, " [style=dashed, label=\"call\"]"),
+                    _ => continue
+                };
+                if self.blocks.contains_key(&target) {
+                    dot += &format!("    b{:03X} -> b{:03X}{};\n", block.start, target, attributes);
+                }
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use asm::assemble;
+
+    const PROGRAM: &str = "
+        CLS
+        LD I, sprite
+        CALL draw
+    loop:
+        SKP V0
+        JP loop
+        LD I, loop
+        LD [I], V1
+        JP V0, loop
+    draw:
+        DRW V0, V1, 3
+        RET
+    sprite:
+        DB 0xF0, 0x90, 0xF0
+    unused:
+        DB 0x12, 0x34";
+
+    fn analyse() -> Analysis {
+        Analysis::new(&assemble(PROGRAM, 0x200).unwrap(), 0x200)
+    }
+
+    #[test]
+    fn code_and_sprites() {
+        let analysis = analyse();
+        assert_eq!(analysis.instructions.iter().cloned().collect::<Vec<_>>(),
+                   vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20A, 0x20C, 0x20E, 0x210, 0x212]);
+        assert_eq!(analysis.ranges(ByteKind::Code), vec![0x200..0x214]);
+        assert_eq!(analysis.ranges(ByteKind::Sprite), vec![0x214..0x217]);
+        assert_eq!(analysis.ranges(ByteKind::Unknown), vec![0x217..0x219]);
+        assert_eq!(analysis.subroutines.iter().cloned().collect::<Vec<_>>(), vec![0x210]);
+        assert_eq!(analysis.computed_jumps.iter().cloned().collect::<Vec<_>>(), vec![0x20E]);
+        assert_eq!(analysis.self_modifying.iter().cloned().collect::<Vec<_>>(), vec![0x20C]);
+    }
+
+    #[test]
+    fn blocks_and_calls() {
+        let analysis = analyse();
+        assert_eq!(analysis.blocks.keys().cloned().collect::<Vec<_>>(), vec![0x200, 0x206, 0x208, 0x20A, 0x210]);
+        assert_eq!(analysis.blocks[&0x200].exits, vec![Exit::Call(0x210), Exit::Next(0x206)]);
+        assert_eq!(analysis.blocks[&0x206].exits, vec![Exit::Next(0x208), Exit::Skip(0x20A)]);
+        assert_eq!(analysis.blocks[&0x20A].instructions, vec![0x20A, 0x20C, 0x20E]);
+        assert_eq!(analysis.blocks[&0x210].exits, vec![Exit::Return]);
+
+        let graph = analysis.call_graph();
+        assert_eq!(graph[&0x200].iter().cloned().collect::<Vec<_>>(), vec![0x210]);
+        assert!(graph[&0x210].is_empty());
+    }
+
+    #[test]
+    fn listing_and_dot() {
+        let analysis = analyse();
+        let listing = analysis.listing();
+        assert_eq!(listing[0], (0x200, 0x00E0, "CLS".to_string()));
+        assert_eq!(listing[10], (0x214, 0xF0, "DB 0xF0 ; ####....".to_string()));
+        assert_eq!(listing[13], (0x217, 0x1234, "JP 0x234".to_string()));
+
+        let dot = analysis.to_dot();
+        assert!(dot.starts_with("digraph program {"));
+        assert!(dot.contains("b200 -> b210 [style=dashed, label=\"call\"];"));
+        assert!(dot.contains("b206 -> b20A [label=\"skip\"];"));
+        assert!(dot.contains("b20A [label=\"20A: LD I, 0x206\\l20C: LD [I], V1\\l20E: JP V0, 0x206\\l\", color=red];"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn bundled_games() {
+        let rom = ::rom::Rom::from_path("examples/games/Pong [Paul Vervalin, 1990].ch8").unwrap();
+        let analysis = Analysis::new(&rom.bytes, 0x200);
+        assert!(!analysis.ranges(ByteKind::Sprite).is_empty());
+        assert!(analysis.blocks.values().all(|block| analysis.instructions.contains(&block.start)));
+        assert!(analysis.instructions.len() * 2 < rom.bytes.len());
+    }
+}
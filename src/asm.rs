@@ -0,0 +1,271 @@
+use core::fmt;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// An error on a particular (1-based) line of the source
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    V(u8),
+    I,
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    HF,
+    B,
+    Value(u16)
+}
+
+/// A line with its label and comment removed
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>
+}
+
+pub(crate) fn parse_number(s: &str) -> Option<u16> {
+    let s = s.trim();
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u16::from_str_radix(&s[2..], 16).ok()
+    } else if s.starts_with('#') || s.starts_with('$') {
+        u16::from_str_radix(&s[1..], 16).ok()
+    } else if s.starts_with("0b") || s.starts_with("0B") {
+        u16::from_str_radix(&s[2..], 2).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn parse_operand(s: &str, labels: &BTreeMap<String, u16>) -> Result<Operand, String> {
+    let upper = s.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::HF,
+        "B" => Operand::B,
+        r if r.len() == 2 && r.starts_with('V') && r.as_bytes()[1].is_ascii_hexdigit() => {
+            Operand::V(u8::from_str_radix(&r[1..], 16).unwrap())
+        },
+        _ => match parse_number(s) {
+            Some(n) => Operand::Value(n),
+            None => match labels.get(s) {
+                Some(&addr) => Operand::Value(addr),
+                None => return Err(format!("unknown operand or label '{}'", s))
+            }
+        }
+    };
+    Ok(operand)
+}
+
+fn check_range(n: u16, max: u16) -> Result<u16, String> {
+    if n > max {
+        Err(format!("0x{:X} is too large, the maximum is 0x{:X}", n, max))
+    } else {
+        Ok(n)
+    }
+}
+
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<u16, String> {
+    use self::Operand::*;
+
+    let xy = |op: u16, x: u8, y: u8, n: u16| op | (x as u16) << 8 | (y as u16) << 4 | n;
+
+    let instruction = match (mnemonic, operands) {
+        ("CLS", &[]) => 0x00E0,
+        ("RET", &[]) => 0x00EE,
+        ("SYS", &[Value(nnn)]) => check_range(nnn, 0xFFF)?,
+        ("JP", &[Value(nnn)]) => 0x1000 | check_range(nnn, 0xFFF)?,
+        ("JP", &[V(0), Value(nnn)]) => 0xB000 | check_range(nnn, 0xFFF)?,
+        ("CALL", &[Value(nnn)]) => 0x2000 | check_range(nnn, 0xFFF)?,
+        ("SE", &[V(x), Value(kk)]) => xy(0x3000, x, 0, 0) | check_range(kk, 0xFF)?,
+        ("SNE", &[V(x), Value(kk)]) => xy(0x4000, x, 0, 0) | check_range(kk, 0xFF)?,
+        ("SE", &[V(x), V(y)]) => xy(0x5000, x, y, 0),
+        ("LD", &[V(x), Value(kk)]) => xy(0x6000, x, 0, 0) | check_range(kk, 0xFF)?,
+        ("ADD", &[V(x), Value(kk)]) => xy(0x7000, x, 0, 0) | check_range(kk, 0xFF)?,
+        ("LD", &[V(x), V(y)]) => xy(0x8000, x, y, 0x0),
+        ("OR", &[V(x), V(y)]) => xy(0x8000, x, y, 0x1),
+        ("AND", &[V(x), V(y)]) => xy(0x8000, x, y, 0x2),
+        ("XOR", &[V(x), V(y)]) => xy(0x8000, x, y, 0x3),
+        ("ADD", &[V(x), V(y)]) => xy(0x8000, x, y, 0x4),
+        ("SUB", &[V(x), V(y)]) => xy(0x8000, x, y, 0x5),
+        ("SHR", &[V(x)]) => xy(0x8000, x, x, 0x6),
+        ("SHR", &[V(x), V(y)]) => xy(0x8000, x, y, 0x6),
+        ("SUBN", &[V(x), V(y)]) => xy(0x8000, x, y, 0x7),
+        ("SHL", &[V(x)]) => xy(0x8000, x, x, 0xE),
+        ("SHL", &[V(x), V(y)]) => xy(0x8000, x, y, 0xE),
+        ("SNE", &[V(x), V(y)]) => xy(0x9000, x, y, 0),
+        ("LD", &[I, Value(nnn)]) => 0xA000 | check_range(nnn, 0xFFF)?,
+        ("RND", &[V(x), Value(kk)]) => xy(0xC000, x, 0, 0) | check_range(kk, 0xFF)?,
+        ("DRW", &[V(x), V(y), Value(n)]) => xy(0xD000, x, y, check_range(n, 0xF)?),
+        ("SKP", &[V(x)]) => xy(0xE09E, x, 0, 0),
+        ("SKNP", &[V(x)]) => xy(0xE0A1, x, 0, 0),
+        ("LD", &[V(x), DT]) => xy(0xF007, x, 0, 0),
+        ("LD", &[V(x), K]) => xy(0xF00A, x, 0, 0),
+        ("LD", &[DT, V(x)]) => xy(0xF015, x, 0, 0),
+        ("LD", &[ST, V(x)]) => xy(0xF018, x, 0, 0),
+        ("ADD", &[I, V(x)]) => xy(0xF01E, x, 0, 0),
+        ("LD", &[F, V(x)]) => xy(0xF029, x, 0, 0),
+        ("LD", &[HF, V(x)]) => xy(0xF030, x, 0, 0),
+        ("LD", &[B, V(x)]) => xy(0xF033, x, 0, 0),
+        ("LD", &[IndirectI, V(x)]) => xy(0xF055, x, 0, 0),
+        ("LD", &[V(x), IndirectI]) => xy(0xF065, x, 0, 0),
+        _ => return Err(format!("invalid instruction '{}' with {} operand(s)", mnemonic, operands.len()))
+    };
+    Ok(instruction)
+}
+
+/// Size in bytes of the output of a statement
+fn statement_size(statement: &Statement) -> usize {
+    match statement.mnemonic.as_str() {
+        "DB" => statement.operands.len(),
+        "DW" => statement.operands.len() * 2,
+        _ => 2
+    }
+}
+
+/// Assemble source using the same syntax `disasm` produces, into a program to be loaded at `start`.
+///
+/// Labels are defined with `name:` and can be used wherever an address is expected,
+/// `;` starts a comment and `DB`/`DW` emit raw bytes/words.
+///
+/// # Examples
+///
+/// ```
+/// let source = "loop: ADD V0, 1\nJP loop ; forever";
+/// assert_eq!(rust_chip8::asm::assemble(source, 0x200).unwrap(), vec![0x70, 0x01, 0x12, 0x00]);
+/// ```
+pub fn assemble(source: &str, start: u16) -> Result<Vec<u8>, AsmError> {
+    let mut labels = BTreeMap::new();
+    let mut statements = Vec::new();
+    let mut addr = start as usize;
+
+    // First pass: find the address of every label
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut code = line.split(';').next().unwrap().trim();
+
+        if let Some(colon) = code.find(':') {
+            let label = code[..colon].trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                return Err(AsmError { line: line_number, message: format!("invalid label '{}'", label) });
+            }
+            if labels.insert(label.to_string(), addr as u16).is_some() {
+                return Err(AsmError { line: line_number, message: format!("label '{}' defined twice", label) });
+            }
+            code = code[colon + 1..].trim();
+        }
+
+        if code.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match code.find(char::is_whitespace) {
+            Some(space) => (&code[..space], code[space..].trim()),
+            None => (code, "")
+        };
+        let operands = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(|s| s.trim()).collect()
+        };
+
+        let statement = Statement { line: line_number, mnemonic: mnemonic.to_ascii_uppercase(), operands };
+        addr += statement_size(&statement);
+        if addr > 0x1000 {
+            return Err(AsmError { line: line_number, message: "program doesn't fit in memory".to_string() });
+        }
+        statements.push(statement);
+    }
+
+    // Second pass: encode
+    let mut bytes = Vec::new();
+    for statement in &statements {
+        let error = |message| AsmError { line: statement.line, message };
+
+        let mut operands = Vec::new();
+        for s in &statement.operands {
+            operands.push(parse_operand(s, &labels).map_err(&error)?);
+        }
+
+        match statement.mnemonic.as_str() {
+            "DB" => for operand in operands {
+                match operand {
+                    Operand::Value(n) => bytes.push(check_range(n, 0xFF).map_err(&error)? as u8),
+                    _ => return Err(error("DB expects numbers".to_string()))
+                }
+            },
+            "DW" => for operand in operands {
+                match operand {
+                    Operand::Value(n) => {
+                        bytes.push((n >> 8) as u8);
+                        bytes.push(n as u8);
+                    },
+                    _ => return Err(error("DW expects numbers".to_string()))
+                }
+            },
+            mnemonic => {
+                let instruction = encode(mnemonic, &operands).map_err(&error)?;
+                bytes.push((instruction >> 8) as u8);
+                bytes.push(instruction as u8);
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disasm::disassemble;
+
+    #[test]
+    fn round_trip_every_instruction() {
+        for instruction in 0..=0xFFFF_u16 {
+            let source = disassemble(instruction);
+            let bytes = assemble(&source, 0x200).unwrap();
+            assert_eq!(bytes, vec![(instruction >> 8) as u8, instruction as u8], "{}", source);
+        }
+    }
+
+    #[test]
+    fn labels_and_data() {
+        let source = "
+            start:
+                LD I, sprite ; point at the data
+                DRW V0, V1, 2
+            end: JP end
+            sprite: DB 0b11110000, #0F
+                    DW 0x1234
+        ";
+        assert_eq!(assemble(source, 0x200).unwrap(),
+                   vec![0xA2, 0x06, 0xD0, 0x12, 0x12, 0x04, 0xF0, 0x0F, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(assemble("CLS\nJP nowhere", 0x200),
+                   Err(AsmError { line: 2, message: "unknown operand or label 'nowhere'".to_string() }));
+        assert_eq!(assemble("LD V0, 0x100", 0x200).unwrap_err().line, 1);
+        assert_eq!(assemble("a:\na:", 0x200).unwrap_err().line, 2);
+        assert_eq!(assemble("FOO V1", 0x200).unwrap_err().line, 1);
+    }
+}
@@ -0,0 +1,137 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use cpu::{Cpu, Display};
+use rom::Rom;
+
+/// What happened when running one ROM
+pub struct BatchResult {
+    pub path: PathBuf,
+    /// The machine after the last frame, or why it couldn't be run
+    pub outcome: Result<Cpu, String>
+}
+
+/// Run every ROM in `paths` for `frames` frames at `speed`, spread over `jobs` threads.
+/// `make_cpu` sets up the machine for each ROM. The results are in the same order as `paths`, and a
+/// ROM that crashes the emulator fails without stopping the others.
+pub fn run_batch<F>(paths: &[PathBuf], jobs: usize, frames: u32, speed: u32, make_cpu: F) -> Vec<BatchResult>
+    where F: Fn(&Rom) -> Result<Cpu, String> + Sync
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..paths.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, paths.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let path = match paths.get(i) {
+                    Some(path) => path,
+                    None => break
+                };
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| run_rom(path, frames, speed, &make_cpu)))
+                    .unwrap_or_else(|payload| Err(format!("crashed: {}", panic_message(&*payload))));
+                results.lock().unwrap()[i] = Some(BatchResult { path: path.clone(), outcome });
+            });
+        }
+    });
+
+    results.into_inner().unwrap().into_iter().map(|result| result.unwrap()).collect()
+}
+
+fn run_rom<F>(path: &Path, frames: u32, speed: u32, make_cpu: &F) -> Result<Cpu, String>
+    where F: Fn(&Rom) -> Result<Cpu, String>
+{
+    let rom = Rom::from_path(path).map_err(|e| e.to_string())?;
+    let mut cpu = make_cpu(&rom)?;
+    for _ in 0..frames {
+        cpu.run_frame(speed);
+    }
+    match cpu.fault() {
+        Some(fault) => Err(format!("stopped at {:#05X}: {}", cpu.prog_counter, fault)),
+        None => Ok(cpu)
+    }
+}
+
+/// What was passed to `panic!`, if it was a string
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown panic", |message| message)
+    }
+}
+
+/// A hash of what's on `display`, the same on every run and every machine, for spotting changes
+pub fn screen_hash(display: &Display) -> u64 {
+    // 64 bit FNV-1a
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    // Only the bytes holding pixels, so a 64 pixel wide screen hashes as it did with 64 bit rows
+    let first = 16 - display.width().div_ceil(8);
+    for row in display.pixels.iter() {
+        for byte in row.to_be_bytes()[first..].iter() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use library::Library;
+
+    #[test]
+    fn threads_give_the_same_results() {
+        let mut paths: Vec<PathBuf> = Library::scan("examples").unwrap().entries.into_iter()
+            .map(|entry| entry.rom.path.unwrap()).take(6).collect();
+        paths.push(PathBuf::from("does/not/exist.ch8"));
+
+        let make_cpu = |rom: &Rom| {
+            let mut cpu = Cpu::new();
+            cpu.seed(1);
+            cpu.load_rom(rom).map_err(|e| e.to_string())?;
+            Ok(cpu)
+        };
+        let hashes = |jobs| -> Vec<Option<u64>> {
+            run_batch(&paths, jobs, 30, 540, make_cpu).iter()
+                .map(|result| result.outcome.as_ref().ok().map(|cpu| screen_hash(&cpu.display)))
+                .collect()
+        };
+
+        let one_thread = hashes(1);
+        assert_eq!(one_thread, hashes(4));
+        assert_eq!(one_thread.len(), 7);
+        assert_eq!(one_thread[6], None);
+        assert!(one_thread[..6].iter().all(|hash| hash.is_some()));
+    }
+
+    #[test]
+    fn crashes_fail_one_rom() {
+        let paths: Vec<PathBuf> = Library::scan("examples").unwrap().entries.into_iter()
+            .map(|entry| entry.rom.path.unwrap()).take(3).collect();
+        let crashing = paths[1].clone();
+        let results = run_batch(&paths, 2, 1, 540, |rom: &Rom| {
+            if rom.path.as_ref() == Some(&crashing) {
+                panic!("bad ROM");
+            }
+            let mut cpu = Cpu::new();
+            cpu.load_rom(rom).map_err(|e| e.to_string())?;
+            Ok(cpu)
+        });
+
+        assert!(results[0].outcome.is_ok() && results[2].outcome.is_ok());
+        assert_eq!(results[1].outcome.as_ref().err().unwrap(), "crashed: bad ROM");
+    }
+
+    #[test]
+    fn hashes_differ() {
+        let mut display = Display::new();
+        let blank = screen_hash(&display);
+        display.pixels[31] = 1;
+        assert_ne!(screen_hash(&display), blank);
+    }
+}
@@ -0,0 +1,231 @@
+use core::fmt;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use asm::parse_number;
+
+/// An error on a particular (1-based) line of a cheat file
+#[derive(Debug, PartialEq)]
+pub struct CheatError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatKind {
+    /// Written after every frame, so the program can't change it for long
+    Freeze,
+    /// Written once, after the ROM is loaded
+    Patch
+}
+
+/// Bytes to write to memory starting at `addr`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    pub kind: CheatKind,
+    pub addr: u16,
+    pub bytes: Vec<u8>
+}
+
+/// A list of cheats, see `Cpu::set_cheats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheats {
+    pub cheats: Vec<Cheat>
+}
+
+impl Cheats {
+    /// Read a cheat file, with a cheat per line: `freeze` or `patch`, an address and the bytes to
+    /// write there. Numbers are written as in `asm`, and `;` starts a comment.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let cheats = rust_chip8::cheats::Cheats::parse("freeze 0x2F0 3 ; lives").unwrap();
+    /// assert_eq!(cheats.cheats[0].bytes, vec![3]);
+    /// ```
+    pub fn parse(source: &str) -> Result<Cheats, CheatError> {
+        let mut cheats = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let error = |message: String| CheatError { line: i + 1, message };
+
+            let mut words = line.split(';').next().unwrap().split_whitespace();
+            let kind = match words.next() {
+                Some(word) if word.eq_ignore_ascii_case("freeze") => CheatKind::Freeze,
+                Some(word) if word.eq_ignore_ascii_case("patch") => CheatKind::Patch,
+                Some(word) => return Err(error(format!("expected freeze or patch, not '{}'", word))),
+                None => continue
+            };
+            let addr = match words.next().map(|word| (word, parse_number(word))) {
+                Some((_, Some(addr))) if addr <= 0xFFF => addr,
+                Some((word, _)) => return Err(error(format!("invalid address '{}'", word))),
+                None => return Err(error("missing address".to_string()))
+            };
+            let mut bytes = Vec::new();
+            for word in words {
+                match parse_number(word) {
+                    Some(byte) if byte <= 0xFF => bytes.push(byte as u8),
+                    _ => return Err(error(format!("invalid byte '{}'", word)))
+                }
+            }
+            if bytes.is_empty() {
+                return Err(error("missing bytes to write".to_string()));
+            }
+            cheats.push(Cheat { kind, addr, bytes });
+        }
+        Ok(Cheats { cheats })
+    }
+
+    fn write(&self, memory: &mut [u8], kind: CheatKind) {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.kind == kind) {
+            for (addr, &byte) in (cheat.addr as usize..).zip(&cheat.bytes) {
+                if let Some(b) = memory.get_mut(addr) {
+                    *b = byte;
+                }
+            }
+        }
+    }
+
+    /// Write the patches, once after loading the ROM
+    pub fn patch(&self, memory: &mut [u8]) {
+        self.write(memory, CheatKind::Patch);
+    }
+
+    /// Write the frozen bytes, after every frame
+    pub fn freeze(&self, memory: &mut [u8]) {
+        self.write(memory, CheatKind::Freeze);
+    }
+}
+
+/// How a byte has to have changed to stay in a `RamSearch`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    /// Holds this value now
+    Equal(u8),
+    Unchanged,
+    Changed,
+    Decreased,
+    Increased
+}
+
+impl Comparison {
+    fn matches(&self, before: u8, now: u8) -> bool {
+        match *self {
+            Comparison::Equal(value) => now == value,
+            Comparison::Unchanged => now == before,
+            Comparison::Changed => now != before,
+            Comparison::Decreased => now < before,
+            Comparison::Increased => now > before
+        }
+    }
+}
+
+/// Finds where a program keeps something, like the number of lives, by taking snapshots of memory
+/// and keeping only the addresses that changed the way the thing did in between.
+///
+/// ```
+/// use rust_chip8::cheats::{Comparison, RamSearch};
+///
+/// let mut memory = vec![3, 3, 3];
+/// let mut search = RamSearch::new(&memory);
+/// // Lose a life
+/// memory[1] = 2;
+/// memory[2] = 9;
+/// assert_eq!(search.narrow(&memory, Comparison::Decreased), &[1]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RamSearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>
+}
+
+impl RamSearch {
+    /// Start with every address in `memory` as a candidate
+    pub fn new(memory: &[u8]) -> RamSearch {
+        RamSearch { snapshot: memory.to_vec(), candidates: (0..memory.len() as u16).collect() }
+    }
+
+    /// Keep the candidates that changed as `comparison` says since the last snapshot,
+    /// and take a new one
+    pub fn narrow(&mut self, memory: &[u8], comparison: Comparison) -> &[u16] {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&addr| match (snapshot.get(addr as usize), memory.get(addr as usize)) {
+            (Some(&before), Some(&now)) => comparison.matches(before, now),
+            _ => false
+        });
+        self.snapshot = memory.to_vec();
+        &self.candidates
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::Cpu;
+    use rom::Rom;
+
+    #[test]
+    fn parse() {
+        let cheats = Cheats::parse("
+            ; Blitz
+            freeze 0x2F0 3
+            PATCH #2A4 0x00 0xE0 ; CLS instead of a jump
+        ").unwrap();
+        assert_eq!(cheats.cheats, vec![
+            Cheat { kind: CheatKind::Freeze, addr: 0x2F0, bytes: vec![3] },
+            Cheat { kind: CheatKind::Patch, addr: 0x2A4, bytes: vec![0x00, 0xE0] }
+        ]);
+
+        let error = |source| Cheats::parse(source).unwrap_err();
+        assert_eq!(error("freeze 0x200 1\nfreze 0x200 1"),
+                   CheatError { line: 2, message: "expected freeze or patch, not 'freze'".to_string() });
+        assert_eq!(error("patch 0x1000 1").message, "invalid address '0x1000'");
+        assert_eq!(error("patch 0x200 0x100").message, "invalid byte '0x100'");
+        assert_eq!(error("patch 0x200").message, "missing bytes to write");
+        assert_eq!(error("freeze").message, "missing address");
+    }
+
+    #[test]
+    fn freeze_and_patch() {
+        // ADD V0, 1 / LD I, 0x300 / LD [I], V1 / JP 0x200
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&Rom::from_bytes(vec![0x70, 0x01, 0xA3, 0x00, 0xF1, 0x55, 0x12, 0x00]).unwrap()).unwrap();
+        // Add 2 instead, and keep 0x301 at 7
+        cpu.set_cheats(Cheats::parse("patch 0x201 2\nfreeze 0x301 7").unwrap());
+        assert_eq!(cpu.memory[0x201], 2);
+
+        // The program writes V1 over 0x301 every time round the loop
+        cpu.run_frame(540);
+        assert_eq!((cpu.memory[0x300], cpu.memory[0x301]), (4, 7));
+        cpu.run_frame(540);
+        assert_eq!((cpu.memory[0x300], cpu.memory[0x301]), (8, 7));
+    }
+
+    #[test]
+    fn search() {
+        let mut memory = vec![5, 5, 5, 5];
+        let mut search = RamSearch::new(&memory);
+        assert_eq!(search.candidates().len(), 4);
+
+        memory[0] = 6;
+        memory[1] = 4;
+        assert_eq!(search.narrow(&memory, Comparison::Changed), &[0, 1]);
+        memory[0] = 7;
+        assert_eq!(search.narrow(&memory, Comparison::Increased), &[0]);
+
+        let mut search = RamSearch::new(&memory);
+        assert_eq!(search.narrow(&memory, Comparison::Unchanged), &[0, 1, 2, 3]);
+        assert_eq!(search.narrow(&memory, Comparison::Equal(5)), &[2, 3]);
+        // A smaller memory can't have the addresses past its end
+        assert_eq!(search.narrow(&memory[..3], Comparison::Unchanged), &[2]);
+    }
+}
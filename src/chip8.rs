@@ -0,0 +1,264 @@
+use cpu::{Cpu, Display, Fault, MemoryMap, MemoryMapError, Quirks, DEFAULT_SPEED};
+use font::Font;
+use rom::{Rom, RomError};
+use timing::Timing;
+
+/// How the machine behaves, fixed until the next `Chip8::new`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub memory_map: MemoryMap,
+    pub font: Font,
+    /// Instructions per second with `Timing::Fixed`
+    pub speed: u32,
+    /// Seed for Cxkk, None for a different sequence of random numbers every run
+    pub seed: Option<u64>
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            quirks: Quirks::new(), timing: Timing::Fixed, memory_map: MemoryMap::new(), font: Font::new(),
+            speed: DEFAULT_SPEED, seed: None
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings::new()
+    }
+}
+
+/// A copy of the registers, for debuggers and tests
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub stack: [u16; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// Stopped at Fx0A until a key is pressed
+    pub waiting_for_key: bool,
+    /// Stopped for good, until `reset` or `load`, because the program did something impossible
+    pub fault: Option<Fault>
+}
+
+/// A CHIP-8 machine for embedding in other programs.
+///
+/// # Examples
+///
+/// ```
+/// use rust_chip8::{Chip8, Rom};
+/// use rust_chip8::chip8::Settings;
+///
+/// let mut chip8 = Chip8::new(Settings::new()).unwrap();
+/// // LD V0, 0xA / LD F, V0 / DRW V1, V1, 5 / JP 0x206
+/// chip8.load(Rom::from_bytes(vec![0x60, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06]).unwrap()).unwrap();
+/// chip8.run_frame();
+/// assert!(chip8.framebuffer().pixel(0, 0));
+/// assert_eq!(chip8.state().v[0], 0x0A);
+/// ```
+pub struct Chip8 {
+    cpu: Cpu,
+    settings: Settings,
+    rom: Option<Rom>
+}
+
+impl Chip8 {
+    /// A machine with nothing loaded, or an error if the memory map can't hold the font
+    pub fn new(settings: Settings) -> Result<Chip8, MemoryMapError> {
+        let cpu = Chip8::fresh_cpu(&settings)?;
+        Ok(Chip8 { cpu, settings, rom: None })
+    }
+
+    fn fresh_cpu(settings: &Settings) -> Result<Cpu, MemoryMapError> {
+        let mut cpu = Cpu::with_memory_map(settings.memory_map.clone())?;
+        cpu.quirks = settings.quirks;
+        cpu.timing = settings.timing;
+        if !cpu.load_font(&settings.font) {
+            let map = &settings.memory_map;
+            return Err(MemoryMapError::NoRoomForFont { font_start: map.font_start, size: map.size });
+        }
+        if let Some(seed) = settings.seed {
+            cpu.seed(seed);
+        }
+        Ok(cpu)
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Load `rom` into a freshly reset machine, ready to run
+    pub fn load(&mut self, rom: Rom) -> Result<(), RomError> {
+        // The settings were checked by `new`
+        let mut cpu = Chip8::fresh_cpu(&self.settings).unwrap();
+        cpu.load_rom(&rom)?;
+        self.cpu = cpu;
+        self.rom = Some(rom);
+        Ok(())
+    }
+
+    /// The ROM given to `load`, if any
+    pub fn rom(&self) -> Option<&Rom> {
+        self.rom.as_ref()
+    }
+
+    /// Start the loaded ROM again from the beginning, with memory, registers and the screen cleared
+    pub fn reset(&mut self) {
+        self.cpu = Chip8::fresh_cpu(&self.settings).unwrap();
+        if let Some(ref rom) = self.rom {
+            // It fitted when it was loaded, so it still does
+            self.cpu.load_rom(rom).unwrap();
+        }
+    }
+
+    /// Run a single instruction, unless waiting for a key or stopped by a fault
+    pub fn step(&mut self) {
+        self.cpu.tick();
+    }
+
+    /// Run a 60th of a second and count the timers down, call this 60 times a second. Once the
+    /// program faults the timers still count down, but no more instructions are run.
+    pub fn run_frame(&mut self) {
+        self.cpu.run_frame(self.settings.speed);
+    }
+
+    /// Why the program was stopped, if it was. ROMs are run as they are, so a broken one stops
+    /// here rather than taking the host program down with it.
+    pub fn fault(&self) -> Option<Fault> {
+        self.cpu.fault()
+    }
+
+    /// Press or release keypad key 0x0 to 0xF. Keys outside that range are ignored.
+    pub fn set_key(&mut self, key: u8, down: bool) {
+        if down {
+            self.cpu.press_key(key);
+        } else {
+            self.cpu.release_key(key);
+        }
+    }
+
+    pub fn framebuffer(&self) -> &Display {
+        &self.cpu.display
+    }
+
+    /// True while the beeper should sound
+    pub fn is_beeping(&self) -> bool {
+        self.cpu.sound_timer > 0
+    }
+
+    pub fn state(&self) -> State {
+        let cpu = &self.cpu;
+        State {
+            v: cpu.v_reg, i: cpu.i_reg, pc: cpu.prog_counter, sp: cpu.stack_pointer, stack: cpu.stack,
+            delay_timer: cpu.delay_timer, sound_timer: cpu.sound_timer, waiting_for_key: !cpu.running,
+            fault: cpu.fault
+        }
+    }
+
+    /// All of memory, read only
+    pub fn memory(&self) -> &[u8] {
+        &self.cpu.memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn chip8_with(bytes: Vec<u8>) -> Chip8 {
+        let mut chip8 = Chip8::new(Settings { seed: Some(1), ..Settings::new() }).unwrap();
+        chip8.load(Rom::from_bytes(bytes).unwrap()).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn step_and_reset() {
+        // LD V3, 0x12 / LD ST, V3 / JP 0x204
+        let mut chip8 = chip8_with(vec![0x63, 0x12, 0xF3, 0x18, 0x12, 0x04]);
+        chip8.step();
+        assert_eq!(chip8.state().v[3], 0x12);
+        assert_eq!(chip8.state().pc, 0x202);
+        assert!(!chip8.is_beeping());
+        chip8.step();
+        assert!(chip8.is_beeping());
+
+        chip8.reset();
+        assert_eq!(chip8.state().pc, 0x200);
+        assert_eq!(chip8.state().v[3], 0);
+        assert_eq!(chip8.memory()[0x200], 0x63);
+    }
+
+    #[test]
+    fn keys() {
+        // LD V0, K / JP 0x202
+        let mut chip8 = chip8_with(vec![0xF0, 0x0A, 0x12, 0x02]);
+        chip8.run_frame();
+        assert!(chip8.state().waiting_for_key);
+        chip8.set_key(0x7, true);
+        chip8.set_key(0x7, false);
+        assert!(!chip8.state().waiting_for_key);
+        assert_eq!(chip8.state().v[0], 0x7);
+    }
+
+    #[test]
+    fn faults() {
+        let fault = |bytes: Vec<u8>, quirks: Quirks| {
+            let mut chip8 = Chip8::new(Settings { quirks, ..Settings::new() }).unwrap();
+            chip8.load(Rom::from_bytes(bytes).unwrap()).unwrap();
+            for _ in 0..2 {
+                chip8.run_frame();
+            }
+            chip8.state().fault
+        };
+        // RET / CALL 0x200
+        assert_eq!(fault(vec![0x00, 0xEE], Quirks::new()), Some(Fault::StackUnderflow));
+        assert_eq!(fault(vec![0x22, 0x00], Quirks::new()), Some(Fault::StackOverflow));
+        // LD I, 0 / LD VF, [I] / JP 0xFFF
+        assert_eq!(fault(vec![0xA0, 0x00, 0xFF, 0x65, 0x1F, 0xFF], Quirks::new()), Some(Fault::PcOutOfBounds(0xFFF)));
+        // JP VF, 0xFFF
+        assert_eq!(fault(vec![0xBF, 0xFF], Quirks::super_chip()), Some(Fault::PcOutOfBounds(0xFFF)));
+        // LD I, 0xFFE / LD B, V0 needs three bytes, LD [I], V1 and LD V1, [I] two
+        let past_end = Some(Fault::MemoryOutOfBounds(0xFFE));
+        assert_eq!(fault(vec![0xAF, 0xFE, 0xF0, 0x33], Quirks::new()), past_end);
+        assert_eq!(fault(vec![0xAF, 0xFE, 0xF1, 0x55], Quirks::new()), None);
+        assert_eq!(fault(vec![0xAF, 0xFE, 0xF2, 0x55], Quirks::new()), past_end);
+        assert_eq!(fault(vec![0xAF, 0xFE, 0xF2, 0x65], Quirks::new()), past_end);
+
+        // Faults stop the program until it's reset
+        let mut chip8 = chip8_with(vec![0x00, 0xEE]);
+        chip8.step();
+        chip8.step();
+        assert_eq!((chip8.fault(), chip8.state().pc), (Some(Fault::StackUnderflow), 0x200));
+        chip8.reset();
+        assert_eq!((chip8.fault(), chip8.state().pc), (None, 0x200));
+    }
+
+    #[test]
+    fn settings_are_used() {
+        let settings = Settings { memory_map: MemoryMap::eti_660(), ..Settings::new() };
+        let mut chip8 = Chip8::new(settings).unwrap();
+        chip8.load(Rom::from_bytes(vec![0x12, 0x00]).unwrap()).unwrap();
+        assert_eq!(chip8.state().pc, 0x600);
+        assert_eq!(chip8.memory()[0x50], 0xF0);
+    }
+
+    #[test]
+    fn memory_map_is_checked() {
+        let new = |memory_map| Chip8::new(Settings { memory_map, ..Settings::new() }).err();
+        assert_eq!(new(MemoryMap { size: 0x200, ..MemoryMap::new() }), None);
+        assert_eq!(new(MemoryMap { size: 0x2000, ..MemoryMap::new() }), Some(MemoryMapError::TooLarge { size: 0x2000 }));
+        assert_eq!(new(MemoryMap { size: 0x100, font_start: 0x50, ..MemoryMap::new() }),
+                   Some(MemoryMapError::NoRoomForFont { font_start: 0x50, size: 0x100 }));
+        // The default font fits, but not one with big hex digits as well
+        let settings = Settings { memory_map: MemoryMap { size: 0xC0, ..MemoryMap::new() },
+                                  font: Font::from_name("octo").unwrap(), ..Settings::new() };
+        assert_eq!(Chip8::new(settings).err(), Some(MemoryMapError::NoRoomForFont { font_start: 0, size: 0xC0 }));
+    }
+}
@@ -0,0 +1,449 @@
+use core::fmt;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use asm::assemble;
+use cpu::{Cpu, Quirks, DEFAULT_SPEED};
+use rom::Rom;
+
+/// What part of the interpreter a check is about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    Opcode,
+    Flags,
+    Quirks,
+    Keypad,
+    Beep
+}
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Category::Opcode => "opcode",
+            Category::Flags => "flags",
+            Category::Quirks => "quirks",
+            Category::Keypad => "keypad",
+            Category::Beep => "beep"
+        }
+    }
+}
+
+/// How the platform a quirks profile is named after behaves, written down separately from `Quirks`
+/// so the checks compare the emulator against the platform rather than against itself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reference {
+    pub shift_uses_vy: bool,
+    pub load_store_increments_i: bool,
+    pub jump_uses_vx: bool,
+    pub logic_resets_vf: bool,
+    /// Sprites drawn past the right or bottom edge wrap around, rather than being clipped
+    pub sprites_wrap: bool
+}
+
+impl Reference {
+    pub fn for_profile(name: &str) -> Option<Reference> {
+        match name {
+            // What this emulator has always done
+            "default" => Some(Reference {
+                shift_uses_vy: false, load_store_increments_i: false, jump_uses_vx: false,
+                logic_resets_vf: false, sprites_wrap: true
+            }),
+            "vip" => Some(Reference {
+                shift_uses_vy: true, load_store_increments_i: true, jump_uses_vx: false,
+                logic_resets_vf: true, sprites_wrap: false
+            }),
+            "schip" => Some(Reference {
+                shift_uses_vy: false, load_store_increments_i: false, jump_uses_vx: true,
+                logic_resets_vf: false, sprites_wrap: false
+            }),
+            _ => None
+        }
+    }
+}
+
+/// One behaviour to check: a small program, and a function that runs it and looks at the result
+pub struct Check {
+    pub category: Category,
+    /// The instruction being checked, e.g. "8xy6"
+    pub opcode: &'static str,
+    pub description: &'static str,
+    source: &'static str,
+    run: fn(&mut Cpu, &Reference) -> Result<(), String>
+}
+
+impl fmt::Debug for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Check({} {})", self.opcode, self.description)
+    }
+}
+
+fn frames(cpu: &mut Cpu, n: u32) {
+    for _ in 0..n {
+        cpu.run_frame(DEFAULT_SPEED);
+    }
+}
+
+fn expect<T: PartialEq + fmt::Debug>(what: &str, got: T, expected: T) -> Result<(), String> {
+    if got == expected {
+        Ok(())
+    } else {
+        Err(format!("{} is {:?}, expected {:?}", what, got, expected))
+    }
+}
+
+fn expect_v(cpu: &Cpu, register: usize, expected: u8) -> Result<(), String> {
+    expect(&format!("V{:X}", register), cpu.v_reg[register], expected)
+}
+
+/// Run the program for a second, long enough for every check to reach its `halt: JP halt`
+fn run(cpu: &mut Cpu) {
+    frames(cpu, 60);
+}
+
+/// The top 8 pixels of each of the first `rows` rows of the screen
+fn screen_bytes(cpu: &Cpu, rows: usize) -> Vec<u8> {
+    cpu.display.pixels[..rows].iter().map(|row| (row >> 56) as u8).collect()
+}
+
+pub const CHECKS: &[Check] = &[
+    Check {
+        category: Category::Opcode, opcode: "00E0", description: "clears the screen",
+        source: "LD V0, 0\nLD F, V0\nDRW V0, V0, 5\nCLS\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect("screen", cpu.display.pixels.iter().all(|&row| row == 0), true)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "2nnn", description: "calls and returns from a subroutine",
+        source: "CALL sub\nLD V1, 2\nhalt: JP halt\nsub: LD V0, 1\nRET",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 0, 1)?;
+            expect_v(cpu, 1, 2)?;
+            expect("SP", cpu.stack_pointer, 0)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "1nnn", description: "jumps",
+        source: "JP over\nLD V0, 1\nover: LD V1, 1\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 0, 0)?;
+            expect_v(cpu, 1, 1)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "3xkk", description: "skips if Vx equals byte",
+        source: "LD V0, 5\nSE V0, 5\nLD V1, 1\nSE V0, 6\nLD V2, 1\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 1, 0)?;
+            expect_v(cpu, 2, 1)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "4xkk", description: "skips if Vx doesn't equal byte",
+        source: "LD V0, 5\nSNE V0, 6\nLD V1, 1\nSNE V0, 5\nLD V2, 1\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 1, 0)?;
+            expect_v(cpu, 2, 1)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "5xy0", description: "skips if Vx equals Vy",
+        source: "LD V0, 5\nLD V1, 5\nLD V2, 6\nSE V0, V1\nLD V3, 1\nSE V0, V2\nLD V4, 1\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 3, 0)?;
+            expect_v(cpu, 4, 1)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "9xy0", description: "skips if Vx doesn't equal Vy",
+        source: "LD V0, 5\nLD V1, 6\nLD V2, 5\nSNE V0, V1\nLD V3, 1\nSNE V0, V2\nLD V4, 1\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 3, 0)?;
+            expect_v(cpu, 4, 1)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "7xkk", description: "adds without carry, leaving VF alone",
+        source: "LD V0, 0xFF\nLD VF, 5\nADD V0, 2\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 0, 1)?;
+            expect_v(cpu, 0xF, 5)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "8xy0", description: "copies, ors, ands and xors registers",
+        source: "LD V0, 0b1100\nLD V1, 0b1010\nLD V2, V0\nOR V2, V1\nLD V3, V0\nAND V3, V1\n\
+                 LD V4, V0\nXOR V4, V1\nLD V5, V1\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 2, 0b1110)?;
+            expect_v(cpu, 3, 0b1000)?;
+            expect_v(cpu, 4, 0b0110)?;
+            expect_v(cpu, 5, 0b1010)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "Annn", description: "sets and adds to I",
+        source: "LD I, 0x345\nLD V0, 0x10\nADD I, V0\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect("I", cpu.i_reg, 0x355)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "Cxkk", description: "masks random numbers with byte",
+        source: "LD V1, 64\nloop: RND V0, 0x0F\nOR V2, V0\nADD V1, 0xFF\nSE V1, 0\nJP loop\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect("bits set by RND", cpu.v_reg[2] & 0xF0, 0)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "Dxyn", description: "draws sprites from the font",
+        source: "LD V0, 0\nLD V1, 0xA\nLD F, V1\nDRW V0, V0, 5\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect("screen", screen_bytes(cpu, 6), vec![0xF0, 0x90, 0xF0, 0x90, 0x90, 0x00])?;
+            expect_v(cpu, 0xF, 0)
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "Fx33", description: "stores the decimal digits of Vx",
+        source: "LD I, 0x300\nLD V0, 123\nLD B, V0\nLD I, 0x303\nLD V0, 7\nLD B, V0\nhalt: JP halt",
+        run: |cpu, _| {
+            cpu.write_bytes(0x300, &[0xAA; 6]);
+            run(cpu);
+            expect("memory at 0x300", cpu.memory[0x300..0x306].to_vec(), vec![1, 2, 3, 0, 0, 7])
+        }
+    },
+    Check {
+        category: Category::Opcode, opcode: "Fx55", description: "stores and loads registers",
+        source: "LD V0, 1\nLD V1, 2\nLD V2, 3\nLD I, 0x300\nLD [I], V2\nLD V0, 0\nLD V1, 0\nLD V2, 0\n\
+                 LD I, 0x300\nLD V1, [I]\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect("memory at 0x300", cpu.memory[0x300..0x304].to_vec(), vec![1, 2, 3, 0])?;
+            expect("V0 to V2", cpu.v_reg[..3].to_vec(), vec![1, 2, 0])
+        }
+    },
+    Check {
+        category: Category::Flags, opcode: "8xy4", description: "sets VF to the carry",
+        source: "LD V0, 0xFF\nLD V1, 1\nADD V0, V1\nLD V2, VF\nLD V3, 1\nADD V3, V1\nLD V4, VF\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 0, 0)?;
+            expect_v(cpu, 2, 1)?;
+            expect_v(cpu, 3, 2)?;
+            expect_v(cpu, 4, 0)
+        }
+    },
+    Check {
+        category: Category::Flags, opcode: "8xy5", description: "sets VF to not borrow",
+        source: "LD V0, 1\nLD V1, 2\nSUB V0, V1\nLD V2, VF\nLD V3, 2\nLD V4, 1\nSUB V3, V4\nLD V5, VF\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 0, 0xFF)?;
+            expect_v(cpu, 2, 0)?;
+            expect_v(cpu, 3, 1)?;
+            expect_v(cpu, 5, 1)
+        }
+    },
+    Check {
+        category: Category::Flags, opcode: "8xy7", description: "sets VF to not borrow",
+        source: "LD V0, 2\nLD V1, 1\nSUBN V0, V1\nLD V2, VF\nLD V3, 1\nLD V4, 2\nSUBN V3, V4\nLD V5, VF\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 0, 0xFF)?;
+            expect_v(cpu, 2, 0)?;
+            expect_v(cpu, 3, 1)?;
+            expect_v(cpu, 5, 1)
+        }
+    },
+    Check {
+        category: Category::Flags, opcode: "8xy6", description: "sets VF to the bit shifted out",
+        source: "LD V0, 0b10000001\nLD V1, V0\nSHR V0, V0\nLD V2, VF\nSHL V1, V1\nLD V3, VF\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 0, 0b0100_0000)?;
+            expect_v(cpu, 1, 0b0000_0010)?;
+            expect_v(cpu, 2, 1)?;
+            expect_v(cpu, 3, 1)
+        }
+    },
+    Check {
+        category: Category::Flags, opcode: "8xyF", description: "sets the flag after the result when Vx is VF",
+        source: "LD VF, 0xFF\nLD V1, 2\nADD VF, V1\nLD V2, VF\nLD VF, 1\nLD V1, 2\nSUB VF, V1\nLD V3, VF\nhalt: JP halt",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 2, 1)?;
+            expect_v(cpu, 3, 0)
+        }
+    },
+    Check {
+        category: Category::Flags, opcode: "Dxyn", description: "sets VF on collision in any row",
+        source: "LD V0, 0\nLD V1, 8\nLD F, V1\nDRW V0, V0, 5\nLD V2, VF\nDRW V0, V0, 5\nLD V3, VF\n\
+                 LD I, sprite\nDRW V0, V0, 2\nLD V4, VF\nDRW V0, V0, 2\nhalt: JP halt\nsprite: DB 0x80, 0x00",
+        run: |cpu, _| {
+            run(cpu);
+            expect_v(cpu, 2, 0)?;
+            expect_v(cpu, 3, 1)?;
+            // Only the first row of the second sprite collides
+            expect_v(cpu, 4, 0)?;
+            expect_v(cpu, 0xF, 1)?;
+            expect("screen", cpu.display.pixels.iter().all(|&row| row == 0), true)
+        }
+    },
+    Check {
+        category: Category::Quirks, opcode: "8xy6", description: "shifts Vy into Vx, or Vx in place",
+        source: "LD V0, 0b100\nLD V1, 0b10000\nSHR V0, V1\nLD V2, 0b100\nSHL V2, V1\nhalt: JP halt",
+        run: |cpu, reference| {
+            run(cpu);
+            if reference.shift_uses_vy {
+                expect_v(cpu, 0, 0b1000)?;
+                expect_v(cpu, 2, 0b100000)
+            } else {
+                expect_v(cpu, 0, 0b10)?;
+                expect_v(cpu, 2, 0b1000)
+            }
+        }
+    },
+    Check {
+        category: Category::Quirks, opcode: "Fx55", description: "moves I past the registers, or leaves it",
+        source: "LD I, 0x300\nLD [I], V2\nLD V4, [I]\nhalt: JP halt",
+        run: |cpu, reference| {
+            run(cpu);
+            expect("I", cpu.i_reg, if reference.load_store_increments_i { 0x308 } else { 0x300 })
+        }
+    },
+    Check {
+        category: Category::Quirks, opcode: "Bnnn", description: "jumps to nnn plus V0, or plus Vx",
+        source: "LD V0, 0x10\nLD V3, 0x20\nJP V0, 0x300",
+        run: |cpu, reference| {
+            // LD V5, 1 / JP self at 0x310, and LD V5, 2 / JP self at 0x320
+            cpu.write_bytes(0x310, &[0x65, 0x01, 0x13, 0x12]);
+            cpu.write_bytes(0x320, &[0x65, 0x02, 0x13, 0x22]);
+            run(cpu);
+            expect_v(cpu, 5, if reference.jump_uses_vx { 2 } else { 1 })
+        }
+    },
+    Check {
+        category: Category::Quirks, opcode: "8xy1", description: "resets VF after OR, AND and XOR, or leaves it",
+        source: "LD VF, 5\nOR V0, V1\nLD V2, VF\nLD VF, 5\nAND V0, V1\nLD V3, VF\nLD VF, 5\nXOR V0, V1\nLD V4, VF\n\
+                 halt: JP halt",
+        run: |cpu, reference| {
+            run(cpu);
+            let vf = if reference.logic_resets_vf { 0 } else { 5 };
+            expect("VF after OR, AND, XOR", cpu.v_reg[2..5].to_vec(), vec![vf; 3])
+        }
+    },
+    Check {
+        category: Category::Quirks, opcode: "Dxyn", description: "wraps or clips sprites at the edge",
+        source: "LD V0, 60\nLD V1, 30\nLD I, sprite\nDRW V0, V1, 3\nhalt: JP halt\nsprite: DB 0xFF, 0xFF, 0xFF",
+        run: |cpu, reference| {
+            run(cpu);
+            let wrapped = if reference.sprites_wrap { 0xF0 } else { 0x00 };
+            expect("row 30, right edge", cpu.display.pixels[30] & 0xF, 0xF)?;
+            expect("row 30, left edge", (cpu.display.pixels[30] >> 56) as u8, wrapped)?;
+            expect("row 0, left edge", (cpu.display.pixels[0] >> 56) as u8, wrapped)
+        }
+    },
+    Check {
+        category: Category::Keypad, opcode: "Ex9E", description: "skips if the key in Vx is pressed",
+        source: "LD V0, 5\nLD V1, 6\nSKP V0\nLD V2, 1\nSKP V1\nLD V3, 1\nhalt: JP halt",
+        run: |cpu, _| {
+            cpu.press_key(5);
+            run(cpu);
+            expect_v(cpu, 2, 0)?;
+            expect_v(cpu, 3, 1)
+        }
+    },
+    Check {
+        category: Category::Keypad, opcode: "ExA1", description: "skips if the key in Vx isn't pressed",
+        source: "LD V0, 5\nLD V1, 6\nSKNP V0\nLD V2, 1\nSKNP V1\nLD V3, 1\nhalt: JP halt",
+        run: |cpu, _| {
+            cpu.press_key(5);
+            run(cpu);
+            expect_v(cpu, 2, 1)?;
+            expect_v(cpu, 3, 0)
+        }
+    },
+    Check {
+        category: Category::Keypad, opcode: "Fx0A", description: "waits for a key and stores it in Vx",
+        source: "LD V0, K\nLD V1, 1\nhalt: JP halt",
+        run: |cpu, _| {
+            frames(cpu, 3);
+            expect("V1 before the key is pressed", cpu.v_reg[1], 0)?;
+            cpu.press_key(0xB);
+            cpu.release_key(0xB);
+            run(cpu);
+            expect_v(cpu, 0, 0xB)?;
+            expect_v(cpu, 1, 1)
+        }
+    },
+    Check {
+        category: Category::Beep, opcode: "Fx18", description: "beeps until the sound timer runs out",
+        source: "LD V0, 10\nLD ST, V0\nhalt: JP halt",
+        run: |cpu, _| {
+            frames(cpu, 5);
+            expect("beeping after 5 frames", cpu.sound_timer > 0, true)?;
+            frames(cpu, 6);
+            expect("beeping after 11 frames", cpu.sound_timer > 0, false)
+        }
+    },
+    Check {
+        category: Category::Beep, opcode: "Fx15", description: "counts the delay timer down at 60Hz",
+        source: "LD V0, 30\nLD DT, V0\nloop: LD V1, DT\nJP loop",
+        run: |cpu, _| {
+            frames(cpu, 10);
+            expect("DT", cpu.delay_timer, 20)?;
+            // Read before the timers counted down at the end of the last frame
+            expect_v(cpu, 1, 21)
+        }
+    }
+];
+
+/// Run `check` on a fresh machine with the quirks for `profile`, one of `Quirks::NAMES`
+pub fn run_check(check: &Check, profile: &str) -> Result<(), String> {
+    let quirks = Quirks::from_name(profile).ok_or_else(|| format!("unknown profile '{}'", profile))?;
+    let reference = Reference::for_profile(profile).ok_or_else(|| format!("no reference for '{}'", profile))?;
+    let bytes = assemble(check.source, 0x200).map_err(|e| format!("check doesn't assemble: {}", e))?;
+    let rom = Rom::from_bytes(bytes).map_err(|e| e.to_string())?;
+
+    let mut cpu = Cpu::new();
+    cpu.quirks = quirks;
+    cpu.seed(0);
+    cpu.load_rom(&rom).map_err(|e| e.to_string())?;
+    (check.run)(&mut cpu, &reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_check_assembles() {
+        for check in CHECKS {
+            assert!(assemble(check.source, 0x200).is_ok(), "{:?}", check);
+        }
+        for &name in Quirks::NAMES {
+            assert!(Reference::for_profile(name).is_some(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn every_check_passes() {
+        for &name in Quirks::NAMES {
+            for check in CHECKS {
+                assert_eq!(run_check(check, name), Ok(()), "{:?} with {}", check, name);
+            }
+        }
+    }
+}
@@ -1,104 +1,384 @@
+use core::fmt;
+use core::ops::Range;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
 use utils::get_nth_hex_digit;
-use rand;
-use std::time;
-use std::fs;
+use rand::{Rng, SeedableRng};
+use rand::prng::XorShiftRng;
+
+use font::{Font, SMALL_FONT_SIZE};
+use profiler::Profile;
+use cheats::Cheats;
+use sprites::SpriteSize;
+use rom::{Rom, RomError};
+use timing::{self, Timing};
 
-const NUM_ROWS: usize = 32;
+/// Largest display, SUPER-CHIP's 128x64 mode. A row of `Display::pixels` holds at most 128.
+const MAX_WIDTH: usize = 128;
+const MAX_HEIGHT: usize = 64;
 
 pub struct Display {
-    // u64 x 32 rows (64x32 monochrome)
-    pub pixels: [u64; NUM_ROWS]
+    width: usize,
+    // A row each, with the leftmost pixel in bit `width - 1`
+    pub(crate) pixels: Vec<u128>
 }
 
 impl Display {
+    /// 64x32, as on the original CHIP-8
     pub fn new() -> Display {
-        Display { pixels: [0; NUM_ROWS] }
+        Display::with_size(64, 32).unwrap()
+    }
+
+    /// A blank display `width` pixels across and `height` down, or None if either is 0 or it's
+    /// bigger than 128x64
+    pub fn with_size(width: usize, height: usize) -> Option<Display> {
+        if width == 0 || height == 0 || width > MAX_WIDTH || height > MAX_HEIGHT {
+            return None;
+        }
+        Some(Display { width, pixels: vec![0; height] })
     }
 
     pub fn clear(&mut self) {
-        for i in 0..NUM_ROWS {
-            self.pixels[i] = 0x00;
+        for row in self.pixels.iter_mut() {
+            *row = 0;
         }
     }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.pixels.len()
+    }
+
+    /// True if the pixel `x` from the left and `y` from the top is on
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y] >> (self.width - 1 - x) & 1 == 1
+    }
+
+    /// XOR `sprite`, a byte a row, onto the screen with its top left corner at (`x`, `y`).
+    /// Coordinates off the screen wrap around to it, and pixels that cross the right or bottom edge
+    /// wrap to the other side if `wrap` is true and are left out if not. Returns true if any pixel
+    /// was turned off.
+    pub fn draw_sprite(&mut self, sprite: &[u8], x: usize, y: usize, wrap: bool) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let (x, y) = (x % width, y % height);
+        let mut collided = false;
+        for (row, &byte) in sprite.iter().enumerate() {
+            if !wrap && y + row >= height {
+                break;
+            }
+            let line = &mut self.pixels[(y + row) % height];
+            for column in (0..8).filter(|column| byte & (0x80 >> column) != 0) {
+                if !wrap && x + column >= width {
+                    break;
+                }
+                let bit: u128 = 1 << (width - 1 - (x + column) % width);
+                collided |= *line & bit != 0;
+                *line ^= bit;
+            }
+        }
+        collided
+    }
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
 }
 
+/// Behaviours that differ between CHIP-8 interpreters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    /// 8xy6/8xyE set Vx = Vy shifted, rather than shifting Vx in place
+    pub shift_uses_vy: bool,
+    /// Fx55/Fx65 leave I pointing at the address after the last register
+    pub load_store_increments_i: bool,
+    /// Bnnn jumps to nnn + Vx, where x is the top nibble of nnn, rather than nnn + V0
+    pub jump_uses_vx: bool,
+    /// 8xy1/8xy2/8xy3 set VF to 0
+    pub logic_resets_vf: bool,
+    /// Dxyn wraps the parts of sprites past the right or bottom edge around to the other side,
+    /// rather than clipping them. Where a sprite starts always wraps.
+    pub sprites_wrap: bool
+}
+
+impl Quirks {
+    /// Names accepted by `from_name`
+    pub const NAMES: &'static [&'static str] = &["default", "vip", "schip"];
+
+    /// The behaviour this emulator has always had
+    pub fn new() -> Quirks {
+        Quirks {
+            shift_uses_vy: false, load_store_increments_i: false,
+            jump_uses_vx: false, logic_resets_vf: false, sprites_wrap: true
+        }
+    }
+
+    /// The original COSMAC VIP interpreter
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true, load_store_increments_i: true,
+            jump_uses_vx: false, logic_resets_vf: true, sprites_wrap: false
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP48
+    pub fn super_chip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false, load_store_increments_i: false,
+            jump_uses_vx: true, logic_resets_vf: false, sprites_wrap: false
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name {
+            "default" => Some(Quirks::new()),
+            "vip" => Some(Quirks::cosmac_vip()),
+            "schip" => Some(Quirks::super_chip()),
+            _ => None
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::new()
+    }
+}
+
+/// Where programs, the font and the interpreter go in memory, which differs between platforms
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryMap {
+    /// Where programs are loaded and start running
+    pub program_start: u16,
+    /// Where the hex font used by Fx29 is stored
+    pub font_start: u16,
+    /// Bytes of memory, at most 4096 since addresses are 12 bits
+    pub size: usize,
+    /// Memory at the end used by the interpreter, which programs can't be loaded into
+    pub reserved: Option<Range<usize>>
+}
+
+impl MemoryMap {
+    /// Names accepted by `from_name`
+    pub const NAMES: &'static [&'static str] = &["default", "vip", "eti660", "schip"];
+
+    /// The layout this emulator has always had, with the font at 0
+    pub fn new() -> MemoryMap {
+        MemoryMap { program_start: 0x200, font_start: 0, size: 4096, reserved: None }
+    }
+
+    /// A 4K COSMAC VIP, where the interpreter keeps its stack, variables and display at the top
+    pub fn cosmac_vip() -> MemoryMap {
+        MemoryMap { program_start: 0x200, font_start: 0x50, size: 4096, reserved: Some(0xEA0..0x1000) }
+    }
+
+    /// The ETI-660, which starts programs at 0x600
+    pub fn eti_660() -> MemoryMap {
+        MemoryMap { program_start: 0x600, font_start: 0x50, size: 4096, reserved: None }
+    }
+
+    /// SUPER-CHIP on the HP48, with the font at 0x50 like most later interpreters
+    pub fn super_chip() -> MemoryMap {
+        MemoryMap { program_start: 0x200, font_start: 0x50, size: 4096, reserved: None }
+    }
+
+    pub fn from_name(name: &str) -> Option<MemoryMap> {
+        match name {
+            "default" => Some(MemoryMap::new()),
+            "vip" => Some(MemoryMap::cosmac_vip()),
+            "eti660" => Some(MemoryMap::eti_660()),
+            "schip" => Some(MemoryMap::super_chip()),
+            _ => None
+        }
+    }
+
+    /// Largest program that fits between `program_start` and the reserved area or the end of memory
+    pub fn max_program_size(&self) -> usize {
+        let end = self.reserved.as_ref().map_or(self.size, |reserved| reserved.start);
+        end.saturating_sub(self.program_start as usize)
+    }
+
+    /// Check that addresses can reach all of memory and the font fits in it
+    pub fn check(&self) -> Result<(), MemoryMapError> {
+        if self.size > 4096 {
+            return Err(MemoryMapError::TooLarge { size: self.size });
+        }
+        if self.font_start as usize + Font::new().size() > self.size {
+            return Err(MemoryMapError::NoRoomForFont { font_start: self.font_start, size: self.size });
+        }
+        Ok(())
+    }
+}
+
+impl Default for MemoryMap {
+    fn default() -> MemoryMap {
+        MemoryMap::new()
+    }
+}
+
+/// Why a `MemoryMap` can't be used
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemoryMapError {
+    /// More memory than 12 bit addresses can reach
+    TooLarge { size: usize },
+    /// The font doesn't fit between `font_start` and the end of memory
+    NoRoomForFont { font_start: u16, size: usize }
+}
+
+impl fmt::Display for MemoryMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MemoryMapError::TooLarge { size } => write!(f, "memory can be at most 4096 bytes, not {}", size),
+            MemoryMapError::NoRoomForFont { font_start, size } =>
+                write!(f, "the font doesn't fit at {:#05X} in {} bytes of memory", font_start, size)
+        }
+    }
+}
+
+/// Why a program was stopped, see `Cpu::fault`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// RET with no subroutine to return from
+    StackUnderflow,
+    /// CALL with 15 subroutines already running
+    StackOverflow,
+    /// The program counter, which is past the end of memory
+    PcOutOfBounds(u16),
+    /// I, where Fx33, Fx55 or Fx65 would have gone past the end of memory
+    MemoryOutOfBounds(u16)
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::StackUnderflow => write!(f, "RET with an empty stack"),
+            Fault::StackOverflow => write!(f, "more than 15 nested CALLs"),
+            Fault::PcOutOfBounds(_) => write!(f, "ran past the end of memory"),
+            Fault::MemoryOutOfBounds(i) => write!(f, "I = {:#05X} reaches past the end of memory", i)
+        }
+    }
+}
+
+/// Default number of instructions executed per second
+pub const DEFAULT_SPEED: u32 = 540;
+/// Display refreshes (and timer ticks) per second
+pub const FRAME_HZ: u32 = 60;
+
+/// Largest program that fits in memory after 0x200, on any platform
+pub const MAX_PROGRAM_SIZE: usize = 0xFFF + 1 - 0x200;
+
 pub struct Cpu {
-    pub v_reg: [u8; 0xF + 1], // 16
-    pub i_reg: u16,
-    pub delay_timer: u8,
-    pub sound_timer: u8,
-    last_timer_tick: time::Instant, // Time of last timer tick
-    pub prog_counter: u16,
-    pub stack_pointer: u8,
-
-    // Program/data memory starts at 0x200
-    pub memory: [u8; 0xFFF + 1], // 4,096 bytes
-    pub stack: [u16; 0xF + 1], // 16
-
-    pub keys: u16, // bitfield for keys pressed
-    pub running: bool, // set to false if waiting for a key press
+    pub(crate) v_reg: [u8; 0xF + 1], // 16
+    pub(crate) i_reg: u16,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) prog_counter: u16,
+    pub(crate) stack_pointer: u8,
+
+    // Program/data memory starts at `memory_map.program_start`
+    pub(crate) memory: Vec<u8>, // `memory_map.size` bytes
+    pub(crate) memory_map: MemoryMap,
+    pub(crate) stack: [u16; 0xF + 1], // 16
+
+    pub(crate) keys: u16, // bitfield for keys pressed
+    pub(crate) running: bool, // set to false if waiting for a key press
     key_pause_register_to_set: u8, // register to set if waiting for key, set by 0xFx0A
-
-    pub display: Display
+    /// Set when the program does something impossible, after which nothing more is run
+    pub(crate) fault: Option<Fault>,
+
+    pub(crate) display: Display,
+    /// Address and length of the sprite drawn most recently
+    pub(crate) last_sprite: Option<(u16, u8)>,
+    /// Every address a sprite has been drawn from since the ROM was loaded, with the size drawn
+    pub(crate) drawn_sprites: BTreeMap<u16, SpriteSize>,
+    pub(crate) quirks: Quirks,
+    pub(crate) timing: Timing,
+    cycles: i32, // machine cycles left over from the last frame with VIP timing, negative if overrun
+    leftover: u32, // 60ths of an instruction left over from earlier frames with fixed timing
+    rng: XorShiftRng, // source for Cxkk, see `seed`
+    profile: Option<Profile>,
+    cheats: Option<Cheats>
 }
 
 impl Cpu {
     pub fn new() -> Cpu {
+        // The default map always has room for the font
+        Cpu::with_memory_map(MemoryMap::new()).unwrap()
+    }
+
+    /// A cpu with memory laid out as given by `memory_map`, if it passes `MemoryMap::check`
+    pub fn with_memory_map(memory_map: MemoryMap) -> Result<Cpu, MemoryMapError> {
+        memory_map.check()?;
         let mut cpu = Cpu {
             v_reg: [0; 16], i_reg: 0, delay_timer: 0, sound_timer: 0,
-            prog_counter: 0, stack_pointer: 0, last_timer_tick: time::Instant::now(),
-            memory: [0; 4096], stack: [0; 16], keys: 0, running: true, key_pause_register_to_set: 0,
-            display: Display::new()
+            prog_counter: 0, stack_pointer: 0,
+            memory: vec![0; memory_map.size], memory_map, stack: [0; 16], keys: 0, running: true, key_pause_register_to_set: 0, fault: None,
+            display: Display::new(), last_sprite: None, drawn_sprites: BTreeMap::new(), quirks: Quirks::new(), timing: Timing::Fixed, cycles: 0, leftover: 0,
+            rng: rng_from_seed(initial_seed()), profile: None, cheats: None
         };
 
-        // Add font data
-        assert!(cpu.write_bytes(0,
-            &[0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-              0x20, 0x60, 0x20, 0x20, 0x70, // 1
-              0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-              0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-              0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-              0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-              0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-              0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-              0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-              0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-              0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-              0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-              0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-              0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-              0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-              0xF0, 0x80, 0xF0, 0x80, 0x80] // F
-        ));
-
-        cpu
-    }
-
-    pub fn from_program_file(filepath: Option<&str>) -> (Cpu, bool) {
-        let mut cpu = Cpu::new();
-        let mut example = false;
-
-        // load program into RAM
-        let bytes = match filepath {
-            Some(filepath) => fs::read(filepath).unwrap(),
-            None => {
-                example = true;
-                vec![0x62, 0x01, 0xF2, 0x1E, 0x12, 0x00]
-            } // example program
-        };
-        cpu.write_bytes(0x200, bytes.as_slice());
-        cpu.prog_counter = 0x200;
-        (cpu, example)
+        cpu.load_font(&Font::new());
+        Ok(cpu)
+    }
+
+    /// Copy `rom` into memory at the program start address and start executing from there
+    pub fn load_rom(&mut self, rom: &Rom) -> Result<(), RomError> {
+        let max = self.memory_map.max_program_size();
+        let start = self.memory_map.program_start;
+        if rom.bytes.len() > max || !self.write_bytes(start, &rom.bytes) {
+            return Err(RomError::TooLarge { size: rom.bytes.len(), max });
+        }
+        self.prog_counter = start;
+        Ok(())
+    }
+
+    /// Put `font` in memory at the font address, the big digits straight after the small ones.
+    /// False if it doesn't fit.
+    pub fn load_font(&mut self, font: &Font) -> bool {
+        let start = self.memory_map.font_start;
+        self.write_bytes(start, &font.small) && self.write_bytes(start + SMALL_FONT_SIZE as u16, &font.big)
+    }
+
+    /// Start the program again: registers, timers, keys and the screen are cleared and the program
+    /// counter goes back to the start, but memory is left as it is
+    pub fn reset(&mut self) {
+        self.v_reg = [0; 16];
+        self.i_reg = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.prog_counter = self.memory_map.program_start;
+        self.stack_pointer = 0;
+        self.stack = [0; 16];
+        self.keys = 0;
+        self.running = true;
+        self.fault = None;
+        self.display.clear();
+        self.last_sprite = None;
+        self.cycles = 0;
+        self.leftover = 0;
+        if let Some(ref mut profile) = self.profile {
+            profile.restart();
+        }
+    }
+
+    /// Make Cxkk produce the same sequence of numbers every run
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = rng_from_seed(seed);
     }
 
-    /// Set key to down, where `key` is between 0x1 and 0xF
+    /// Set key to down, where `key` is between 0x0 and 0xF
     /// If the cpu is currently paused due to a call to 0xFx0A, it will set register Vx to key
     pub fn press_key(&mut self, key: u8) -> bool {
-        if !(0x1 <= key && key <= 0xF) {
+        if key > 0xF {
             return false
         }
 
-        self.keys |= 0x1 << (key - 1);
+        self.keys |= 0x1 << key;
 
         if !self.running {
             self.v_reg[self.key_pause_register_to_set as usize] = key;
@@ -108,9 +388,19 @@ impl Cpu {
         true
     }
 
+    /// Set key to up, where `key` is between 0x0 and 0xF
+    pub fn release_key(&mut self, key: u8) -> bool {
+        if key > 0xF {
+            return false
+        }
+
+        self.keys &= !(0x1 << key);
+        true
+    }
+
     pub fn write_bytes(&mut self, addr: u16, bytes: &[u8]) -> bool {
         let addr = addr as usize;
-        if addr + bytes.len() > 0xFFF + 1 {
+        if addr + bytes.len() > self.memory.len() {
             return false;
         }
 
@@ -120,31 +410,104 @@ impl Cpu {
         true
     }
 
+    /// Count down the delay and sound timers, should be called 60 times a second
+    pub fn tick_timers(&mut self) {
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+    }
+
+    /// The 2-byte instruction at the program counter, msb first, or 0 past the end of memory
+    pub fn next_instruction(&self) -> u16 {
+        let byte = |addr: usize| self.memory.get(addr).cloned().unwrap_or(0) as u16;
+        byte(self.prog_counter as usize) << 8 | byte(self.prog_counter as usize + 1)
+    }
+
+    /// Why the program was stopped, if it was
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
     /// Execute next 2-byte instruction from memory, msb first.
-    /// Only execute if `self.running` is true
+    /// Only execute if `self.running` is true and there's been no fault
     pub fn tick(&mut self) {
         // NOTE: "If a program includes sprite data, it should be padded so any
         // instructions following it will be properly situated in RAM."
 
-        if self.running {
-            // delay and sound timers
-            if self.last_timer_tick.elapsed().subsec_millis() > 17 { // 1 / 60 == 0.1666666667
-                self.last_timer_tick = time::Instant::now();
+        if self.running && self.fault.is_none() {
+            if self.prog_counter as usize + 2 > self.memory.len() {
+                self.fault = Some(Fault::PcOutOfBounds(self.prog_counter));
+                return;
+            }
+            let ins = self.next_instruction();
+            if let Some(ref mut profile) = self.profile {
+                profile.instruction(self.prog_counter, ins);
+            }
+            self.prog_counter += 2;
+            self.execute(ins);
+            if self.fault.is_some() {
+                // Leave PC at the instruction that faulted
+                self.prog_counter -= 2;
+            }
+        }
+    }
 
-                if self.sound_timer > 0 {
-                    self.sound_timer -= 1;
-                }
-                if self.delay_timer > 0 {
-                    self.delay_timer -= 1;
+    /// Run one frame's worth of instructions and count down the timers once.
+    /// `speed` is ignored with VIP timing, where instructions take as long as they did on the VIP.
+    pub fn run_frame(&mut self, speed: u32) {
+        match self.timing {
+            Timing::Fixed => {
+                // Speeds that aren't a multiple of 60 run an extra instruction every few frames
+                let speed = speed + self.leftover;
+                for _ in 0..speed / FRAME_HZ {
+                    self.tick();
                 }
-            }
+                self.leftover = speed % FRAME_HZ;
+            },
+            Timing::CosmacVip => self.run_vip_frame()
+        }
+        self.tick_timers();
+        if let Some(ref mut profile) = self.profile {
+            profile.end_frame(!self.running);
+        }
+        if let Some(ref cheats) = self.cheats {
+            cheats.freeze(&mut self.memory);
+        }
+    }
+
+    /// Write the patches in `cheats` to memory now, and the frozen bytes now and after every frame
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        cheats.patch(&mut self.memory);
+        cheats.freeze(&mut self.memory);
+        self.cheats = Some(cheats);
+    }
 
-            let ins = (self.memory[self.prog_counter as usize] as u16) << 8
-                | self.memory[self.prog_counter as usize + 1] as u16;
+    /// Count every instruction from now on, see `profiler::Profile`. Frames are counted by `run_frame`.
+    pub fn start_profiling(&mut self) {
+        self.profile = Some(Profile::new(self.memory.len(), self.memory_map.program_start));
+    }
 
-            self.prog_counter += 2;
-            self.execute(ins);
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Run as many instructions as the COSMAC VIP would in one frame. An instruction that runs past
+    /// the end of the frame takes time from the next one, and after drawing a sprite the rest of the
+    /// frame is spent waiting, as the VIP interpreter waited for the display interrupt.
+    pub fn run_vip_frame(&mut self) {
+        self.cycles += timing::VIP_CYCLES_PER_FRAME as i32;
+        while self.cycles > 0 && self.running && self.fault.is_none() {
+            let ins = self.next_instruction();
+            self.cycles -= timing::vip_cycles(self, ins) as i32;
+            self.tick();
+            if ins & 0xF000 == 0xD000 {
+                break;
+            }
         }
+        self.cycles = self.cycles.min(0);
     }
 
     /// Execute two-byte instruction given by `instruction`
@@ -155,13 +518,23 @@ impl Cpu {
             0x00E0 => self.display.clear(),
             // RET: return from subroutine
             0x00EE => {
+                if self.stack_pointer == 0 {
+                    self.fault = Some(Fault::StackUnderflow);
+                    return;
+                }
                 self.prog_counter = self.stack[self.stack_pointer as usize];
                 self.stack_pointer -= 1;
             }
+            // SYS 0x0nnn: call a machine code routine on the original hardware, ignored
+            a if a < 0x1000 => {},
             // JMP 0x1nnn: set PC to nnn
             a if a < 0x2000 => self.prog_counter = a - 0x1000,
             // CALL 0x2nnn: call subroutine at nnn
             a if a < 0x3000 => {
+                if self.stack_pointer as usize + 1 >= self.stack.len() {
+                    self.fault = Some(Fault::StackOverflow);
+                    return;
+                }
                 self.stack_pointer += 1;
                 self.stack[self.stack_pointer as usize] = self.prog_counter;
                 self.prog_counter = a - 0x2000;
@@ -186,12 +559,12 @@ impl Cpu {
                     self.prog_counter += 2;
                 }
             }
-            // 5xy0 - SE Vx, Vy: skip if Vx == Vy
-            a if a < 0x6000 && get_nth_hex_digit(a as u32, 0) == 0 => {
+            // 5xy0 - SE Vx, Vy: skip if Vx == Vy. Other 5xyn aren't instructions and do nothing.
+            a if a < 0x6000 => {
                 let x = get_nth_hex_digit(a as u32, 2);
                 let y = get_nth_hex_digit(a as u32, 1);
 
-                if self.v_reg[x as usize] == self.v_reg[y as usize] {
+                if get_nth_hex_digit(a as u32, 0) == 0 && self.v_reg[x as usize] == self.v_reg[y as usize] {
                     self.prog_counter += 2;
                 }
             }
@@ -217,11 +590,26 @@ impl Cpu {
                     // 8xy0 - LD Vx, Vy: set Vx = Vy
                     0 => self.v_reg[x as usize] = self.v_reg[y as usize],
                     // 8xy1 - OR Vx, Vy: set Vx = Vx OR Vy
-                    1 => self.v_reg[x as usize] = self.v_reg[x as usize] | self.v_reg[y as usize],
+                    1 => {
+                        self.v_reg[x as usize] = self.v_reg[x as usize] | self.v_reg[y as usize];
+                        if self.quirks.logic_resets_vf {
+                            self.v_reg[0xF] = 0;
+                        }
+                    },
                     // 8xy2 - AND Vx, Vy: set Vx = Vx AND Vy
-                    2 => self.v_reg[x as usize] = self.v_reg[x as usize] & self.v_reg[y as usize],
+                    2 => {
+                        self.v_reg[x as usize] = self.v_reg[x as usize] & self.v_reg[y as usize];
+                        if self.quirks.logic_resets_vf {
+                            self.v_reg[0xF] = 0;
+                        }
+                    },
                     // 8xy3 - XOR Vx, Vy: set Vx = Vx XOR Vy
-                    3 => self.v_reg[x as usize] = self.v_reg[x as usize] ^ self.v_reg[y as usize],
+                    3 => {
+                        self.v_reg[x as usize] = self.v_reg[x as usize] ^ self.v_reg[y as usize];
+                        if self.quirks.logic_resets_vf {
+                            self.v_reg[0xF] = 0;
+                        }
+                    },
                     // 8xy4 - ADD Vx, Vy: set Vx = Vx + Vy, set VF = carry
                     4 => {
                         let (res, carry) = self.v_reg[x as usize].overflowing_add(self.v_reg[y as usize]);
@@ -236,8 +624,10 @@ impl Cpu {
                     },
                     // 8xy6 - SHR Vx {, Vy}: set Vx = Vx SHR 1
                     6 => {
-                        self.v_reg[0xF] = (self.v_reg[x as usize] & 1 == 1) as u8;
-                        self.v_reg[x as usize] >>= 1;
+                        let source = if self.quirks.shift_uses_vy { y } else { x };
+                        let value = self.v_reg[source as usize];
+                        self.v_reg[x as usize] = value >> 1;
+                        self.v_reg[0xF] = (value & 1 == 1) as u8;
                     },
                     // 8xy7 - SUBN Vx, Vy: set Vx = Vy - Vx, set VF = NOT borrow
                     7 => {
@@ -247,18 +637,20 @@ impl Cpu {
                     },
                     //8xyE - SHL Vx {, Vy}: set Vx = Vx SHL 1
                     0xE => {
-                        self.v_reg[0xF] = (self.v_reg[x as usize] & 0b1000_0000 > 0) as u8;
-                        self.v_reg[x as usize] <<= 1;
+                        let source = if self.quirks.shift_uses_vy { y } else { x };
+                        let value = self.v_reg[source as usize];
+                        self.v_reg[x as usize] = value << 1;
+                        self.v_reg[0xF] = (value & 0b1000_0000 > 0) as u8;
                     },
                     _ => {}
                 }
             },
-            // 9xy0 - SNE Vx, Vy: skip next instruction if Vx != Vy
-            a if a < 0xA000 && get_nth_hex_digit(a as u32, 0) == 0 => {
+            // 9xy0 - SNE Vx, Vy: skip next instruction if Vx != Vy. Other 9xyn do nothing.
+            a if a < 0xA000 => {
                 let x = get_nth_hex_digit(a as u32, 2);
                 let y = get_nth_hex_digit(a as u32, 1);
 
-                if self.v_reg[x as usize] != self.v_reg[y as usize] {
+                if get_nth_hex_digit(a as u32, 0) == 0 && self.v_reg[x as usize] != self.v_reg[y as usize] {
                     self.prog_counter += 2;
                 }
             },
@@ -270,14 +662,15 @@ impl Cpu {
             // Bnnn - JP V0, addr: jump to location nnn + V0
             a if a < 0xC000 => {
                 let nnn = a - 0xB000;
-                self.prog_counter = (self.v_reg[0] as u16) + nnn;
+                let x = if self.quirks.jump_uses_vx { get_nth_hex_digit(a as u32, 2) } else { 0 };
+                self.prog_counter = (self.v_reg[x as usize] as u16) + nnn;
             },
             // Cxkk - RND Vx, byte: set Vx = random byte AND kk
             a if a < 0xD000 => {
                 let xkk = a - 0xC000;
                 let x = get_nth_hex_digit(xkk as u32, 2) as u16;
                 let kk = (xkk - (x << 4 * 2)) as u8;
-                self.v_reg[x as usize] = rand::random::<u8>() & kk;
+                self.v_reg[x as usize] = self.rng.gen::<u8>() & kk;
             },
             // Dxyn - DRW Vx, Vy, nibble
             // display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
@@ -288,36 +681,21 @@ impl Cpu {
 
                 let x = self.v_reg[x as usize];
                 let y = self.v_reg[y as usize];
-
-                for i in 0..n {
-                    let mut line = self.memory[self.i_reg as usize + i as usize] as u64;
-                    let mut mask = 0b1111_1111 as u64;
-                    // Wrap sprite around edge of screen
-                    // wrapping_sub etc. is to make sure there are no runtime overflow errors,
-                    // it is not (directly) related to the sprite wrapping around the screen!
-                    let x_to_edge = 64_u8.wrapping_sub(x);
-                    if x_to_edge < 8 {
-                        let shift = (64 - 8_u32).wrapping_add(x_to_edge as u32);
-                        line = line << shift | line >> (8 - x_to_edge);
-                        mask = mask << shift | mask >> (8 - x_to_edge);
-                    } else {
-                        let shift = (64 - 8_u32).wrapping_sub(x as u32);
-                        line = line.wrapping_shl(shift);
-                        mask = mask.wrapping_shl(shift);
-                    }
-                    let pixel_row = (y as usize + i as usize) % 32;
-
-                    let overwrote = line & self.display.pixels[pixel_row] & mask != 0;
-                    self.v_reg[0xF] = overwrote as u8;
-
-                    self.display.pixels[pixel_row] ^= line;
-                }
+                self.last_sprite = Some((self.i_reg, n));
+                self.drawn_sprites.insert(self.i_reg, SpriteSize::from_nibble(n));
+
+                // Rows past the end of memory are left out
+                let start = (self.i_reg as usize).min(self.memory.len());
+                let end = (start + n as usize).min(self.memory.len());
+                let collided = self.display.draw_sprite(&self.memory[start..end], x as usize, y as usize,
+                                                        self.quirks.sprites_wrap);
+                self.v_reg[0xF] = collided as u8;
             },
             // Ex9E - SKP Vx: skip next instruction if key with the value of Vx is pressed
             a if a & 0xF0FF == 0xE09E => {
                 let x = get_nth_hex_digit(a as u32, 2);
 
-                if self.keys & ((0b0000_0001 << self.v_reg[x as usize]) as u16) != 0 {
+                if self.keys & (0b0000_0001 << (self.v_reg[x as usize] & 0xF)) != 0 {
                     self.prog_counter += 2;
                 }
             },
@@ -325,7 +703,7 @@ impl Cpu {
             a if a & 0xF0FF == 0xE0A1 => {
                 let x = get_nth_hex_digit(a as u32, 2);
 
-                if self.keys & ((0b0000_0001 << self.v_reg[x as usize]) as u16) == 0 {
+                if self.keys & (0b0000_0001 << (self.v_reg[x as usize] & 0xF)) == 0 {
                     self.prog_counter += 2;
                 }
             },
@@ -353,46 +731,86 @@ impl Cpu {
             // Fx1E - ADD I, Vx: set I = I + Vx
             a if a & 0xF0FF == 0xF01E => {
                 let x = get_nth_hex_digit(a as u32, 2);
-                self.i_reg += self.v_reg[x as usize] as u16;
+                self.i_reg = self.i_reg.wrapping_add(self.v_reg[x as usize] as u16);
             },
             // Fx29 - LD F, Vx: set I = location of sprite for digit Vx
             a if a & 0xF0FF == 0xF029 => {
                 let x = get_nth_hex_digit(a as u32, 2);
-                self.i_reg = self.v_reg[x as usize] as u16 * 5;
+                self.i_reg = self.memory_map.font_start + self.v_reg[x as usize] as u16 * 5;
+            },
+            // Fx30 - LD HF, Vx: set I = location of the big sprite for digit Vx
+            a if a & 0xF0FF == 0xF030 => {
+                let x = get_nth_hex_digit(a as u32, 2);
+                self.i_reg = self.memory_map.font_start + SMALL_FONT_SIZE as u16 + self.v_reg[x as usize] as u16 * 10;
             },
             // Fx33 - LD B, Vx: store BCD representation of Vx in memory locations I, I+1, and I+2
             a if a & 0xF0FF == 0xF033 => {
                 let x = get_nth_hex_digit(a as u32, 2);
                 let n = self.v_reg[x as usize];
-                // Convert to string to access digits
-                let s: String = n.to_string();
-
-                for (i, ch) in s.chars().enumerate() {
-                    let n = ch.to_digit(10).unwrap() as u8;
-                    self.memory[self.i_reg as usize + i] = n;
+                let i = self.i_reg as usize;
+                if i + 3 > self.memory.len() {
+                    self.fault = Some(Fault::MemoryOutOfBounds(self.i_reg));
+                    return;
                 }
+                self.memory[i] = n / 100;
+                self.memory[i + 1] = n / 10 % 10;
+                self.memory[i + 2] = n % 10;
             },
             // Fx55 - LD [I], Vx: store registers V0 through Vx in memory starting at location I
             a if a & 0xF0FF == 0xF055 => {
                 let x = get_nth_hex_digit(a as u32, 2);
-                // TODO: bounds check
+                if self.i_reg as usize + x as usize + 1 > self.memory.len() {
+                    self.fault = Some(Fault::MemoryOutOfBounds(self.i_reg));
+                    return;
+                }
                 for i in 0..(x + 1) {
                     self.memory[self.i_reg as usize + i as usize] = self.v_reg[i as usize];
                 }
+                if self.quirks.load_store_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
+                }
             },
             // Fx65 - LD Vx, [I]: read registers V0 through Vx from memory starting at location I
             a if a & 0xF0FF == 0xF065 => {
                 let x = get_nth_hex_digit(a as u32, 2);
-                // TODO: bounds check
+                if self.i_reg as usize + x as usize + 1 > self.memory.len() {
+                    self.fault = Some(Fault::MemoryOutOfBounds(self.i_reg));
+                    return;
+                }
                 for i in 0..(x + 1) {
                     self.v_reg[i as usize] = self.memory[self.i_reg as usize + i as usize];
                 }
+                if self.quirks.load_store_increments_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
+                }
             }
             _ => {}
         }
     }
 }
 
+/// A different seed every run
+#[cfg(feature = "std")]
+fn initial_seed() -> u64 {
+    ::rand::random()
+}
+
+/// Without `std` there's nowhere to get a random seed from, call `Cpu::seed` to pick one
+#[cfg(not(feature = "std"))]
+fn initial_seed() -> u64 {
+    0
+}
+
+fn rng_from_seed(seed: u64) -> XorShiftRng {
+    // xorshift can't be seeded with all zeros, so pad the seed with its complement
+    let mut bytes = [0; 16];
+    for i in 0..8 {
+        bytes[i] = (seed >> (8 * i)) as u8;
+        bytes[i + 8] = !bytes[i];
+    }
+    XorShiftRng::from_seed(bytes)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -405,6 +823,33 @@ mod tests {
         assert_eq!(cpu.display.pixels[0], 0);
     }
 
+    #[test]
+    fn speeds_between_multiples_of_60() {
+        // ADD V0, 1 over and over, counting instructions
+        let count = |speed, frames| {
+            let mut cpu = Cpu::new();
+            cpu.load_rom(&Rom::from_bytes([0x70, 0x01].repeat(100)).unwrap()).unwrap();
+            for _ in 0..frames {
+                cpu.run_frame(speed);
+            }
+            cpu.v_reg[0]
+        };
+        assert_eq!(count(1000, 3), 50);
+        assert_eq!(count(30, 1), 0);
+        assert_eq!(count(30, 2), 1);
+        assert_eq!(count(1, 120), 2);
+    }
+
+    #[test]
+    fn not_instructions() {
+        let mut cpu = Cpu::new();
+        // SYS 0x123, and 5xyn and 9xyn without a 0 at the end
+        for &ins in &[0x0123, 0x5121, 0x9121] {
+            cpu.execute(ins);
+        }
+        assert_eq!((cpu.prog_counter, cpu.fault()), (0, None));
+    }
+
     #[test]
     fn ins_jmp() {
         let mut cpu = Cpu::new();
@@ -575,12 +1020,55 @@ mod tests {
         assert_eq!(cpu.v_reg[0xF], 1);
     }
 
+    #[test]
+    fn ins_shift_quirk() {
+        let mut cpu = Cpu::new();
+        cpu.quirks = Quirks::cosmac_vip();
+        cpu.execute(0x6201);
+        cpu.execute(0x6381);
+        cpu.execute(0x8236);
+        assert_eq!(cpu.v_reg[0x2], 0x40);
+        assert_eq!(cpu.v_reg[0xF], 1);
+        cpu.execute(0x823E);
+        assert_eq!(cpu.v_reg[0x2], 0x02);
+        assert_eq!(cpu.v_reg[0xF], 1);
+    }
+
+    #[test]
+    fn ins_logic_quirk() {
+        let mut cpu = Cpu::new();
+        cpu.quirks = Quirks::cosmac_vip();
+        for &ins in &[0x8231, 0x8232, 0x8233] {
+            cpu.execute(0x6F01);
+            cpu.execute(ins);
+            assert_eq!(cpu.v_reg[0xF], 0);
+        }
+    }
+
     #[test]
     fn ins_jp() {
         let mut cpu = Cpu::new();
         cpu.execute(0x6034);
         cpu.execute(0xB123);
         assert_eq!(cpu.prog_counter, 0x34 + 0x123);
+
+        cpu.quirks = Quirks::super_chip();
+        cpu.execute(0x6105);
+        cpu.execute(0xB123);
+        assert_eq!(cpu.prog_counter, 0x05 + 0x123);
+    }
+
+    #[test]
+    fn ins_rnd_seeded() {
+        let mut a = Cpu::new();
+        let mut b = Cpu::new();
+        a.seed(1234);
+        b.seed(1234);
+        for _ in 0..8 {
+            a.execute(0xC0FF);
+            b.execute(0xC0FF);
+            assert_eq!(a.v_reg[0], b.v_reg[0]);
+        }
     }
 
     #[test]
@@ -603,7 +1091,7 @@ mod tests {
 
         assert_eq!(cpu.v_reg[0xF], 0);
         for i in 0..3 {
-            assert_eq!(cpu.display.pixels[y as usize + i], (sprite[i] as u64) << (64 - 8 - x));
+            assert_eq!(cpu.display.pixels[y as usize + i], (sprite[i] as u128) << (64 - 8 - x));
         }
 
         // Overwriting current pixels
@@ -620,7 +1108,7 @@ mod tests {
         cpu.execute(0xD013);
         for i in 0..3 {
             assert_eq!(cpu.display.pixels[y as usize + i],
-                       (sprite[i] as u64) >> (8 - x_to_edge) | (sprite[i] as u64) << (64 - 8 + x_to_edge));
+                       ((sprite[i] as u64) >> (8 - x_to_edge) | (sprite[i] as u64) << (64 - 8 + x_to_edge)) as u128);
         }
         assert_eq!(cpu.v_reg[0xF], 0);
 
@@ -638,9 +1126,9 @@ mod tests {
         cpu.execute(0x6100 + y);
         cpu.execute(0xD013);
         let shift = 64 - 8 - x;
-        assert_eq!(cpu.display.pixels[y as usize], (sprite[0] as u64) << shift);
-        assert_eq!(cpu.display.pixels[0], (sprite[1] as u64) << shift);
-        assert_eq!(cpu.display.pixels[1], (sprite[2] as u64) << shift);
+        assert_eq!(cpu.display.pixels[y as usize], (sprite[0] as u128) << shift);
+        assert_eq!(cpu.display.pixels[0], (sprite[1] as u128) << shift);
+        assert_eq!(cpu.display.pixels[1], (sprite[2] as u128) << shift);
 
         // Overwriting current pixels
         cpu.execute(0xD013);
@@ -650,6 +1138,112 @@ mod tests {
         }
     }
 
+    /// The pixels that are on, as (x, y) from the top left
+    fn lit(display: &Display) -> Vec<(usize, usize)> {
+        let mut lit = Vec::new();
+        for y in 0..display.height() {
+            lit.extend((0..display.width()).filter(|&x| display.pixel(x, y)).map(|x| (x, y)));
+        }
+        lit
+    }
+
+    /// Both resolutions, the original 64x32 and SUPER-CHIP's 128x64
+    const SIZES: [(usize, usize); 2] = [(64, 32), (128, 64)];
+
+    #[test]
+    fn display_sizes() {
+        assert_eq!(Display::with_size(128, 64).map(|d| (d.width(), d.height())), Some((128, 64)));
+        for &(w, h) in &[(0, 32), (64, 0), (129, 64), (128, 65)] {
+            assert!(Display::with_size(w, h).is_none(), "{}x{}", w, h);
+        }
+    }
+
+    /// x, y, whether sprites wrap and the pixels lit
+    type EdgeCase = (usize, usize, bool, Vec<(usize, usize)>);
+
+    /// Where the corners of `[0x81, 0x81]` are lit when drawn at x, y on a `w` by `h` display, with
+    /// and without wrapping
+    fn edge_cases(w: usize, h: usize) -> Vec<EdgeCase> {
+        vec![
+            (10, 5, true, vec![(10, 5), (17, 5), (10, 6), (17, 6)]),
+            (10, 5, false, vec![(10, 5), (17, 5), (10, 6), (17, 6)]),
+            // Touching the bottom right corner without crossing it
+            (w - 8, h - 2, false, vec![(w - 8, h - 2), (w - 1, h - 2), (w - 8, h - 1), (w - 1, h - 1)]),
+            // Starting off the screen always wraps
+            (w + 10, h + 5, false, vec![(10, 5), (17, 5), (10, 6), (17, 6)]),
+            (255, 255, false, vec![(w - 1, h - 1)]),
+            // Across the right edge
+            (w - 4, 5, true, vec![(3, 5), (w - 4, 5), (3, 6), (w - 4, 6)]),
+            (w - 4, 5, false, vec![(w - 4, 5), (w - 4, 6)]),
+            // Across the bottom edge
+            (10, h - 1, true, vec![(10, 0), (17, 0), (10, h - 1), (17, h - 1)]),
+            (10, h - 1, false, vec![(10, h - 1), (17, h - 1)]),
+            // Across the corner
+            (w - 4, h - 1, true, vec![(3, 0), (w - 4, 0), (3, h - 1), (w - 4, h - 1)]),
+            (w - 4, h - 1, false, vec![(w - 4, h - 1)])
+        ]
+    }
+
+    #[test]
+    fn draw_edges() {
+        for &(w, h) in &SIZES {
+            for (x, y, wrap, expected) in edge_cases(w, h) {
+                let case = format!("{}x{} at ({}, {}), wrapping: {}", w, h, x, y, wrap);
+                let mut cpu = Cpu::new();
+                cpu.display = Display::with_size(w, h).unwrap();
+                cpu.quirks.sprites_wrap = wrap;
+                cpu.write_bytes(0x300, &[0x81, 0x81]);
+                cpu.execute(0xA300);
+                cpu.execute(0x6000 + x as u16);
+                cpu.execute(0x6100 + y as u16);
+                cpu.execute(0xD012);
+                assert_eq!(lit(&cpu.display), expected, "{}", case);
+                assert_eq!(cpu.v_reg[0xF], 0, "{}", case);
+
+                // Drawing it again erases it, colliding wherever it was drawn
+                cpu.execute(0xD012);
+                assert_eq!(lit(&cpu.display), vec![], "{}", case);
+                assert_eq!(cpu.v_reg[0xF], 1, "{}", case);
+            }
+        }
+    }
+
+    #[test]
+    fn draw_collisions() {
+        for &(w, h) in &SIZES {
+            let mut cpu = Cpu::new();
+            cpu.display = Display::with_size(w, h).unwrap();
+            cpu.write_bytes(0x300, &[0x80, 0x00, 0x00, 0x01]);
+            cpu.execute(0xA300);
+            cpu.execute(0xD001);
+            // Only the first row collides, and later rows don't clear VF
+            cpu.execute(0xD004);
+            assert_eq!(cpu.v_reg[0xF], 1);
+            assert_eq!(lit(&cpu.display), vec![(7, 3)]);
+            // Only the last row collides
+            cpu.execute(0xD004);
+            assert_eq!(cpu.v_reg[0xF], 1);
+            assert_eq!(lit(&cpu.display), vec![(0, 0)]);
+            cpu.display.clear();
+            cpu.execute(0xD004);
+            assert_eq!(cpu.v_reg[0xF], 0);
+
+            // Clipped pixels can't collide with what's on the other side
+            cpu.display.clear();
+            cpu.quirks.sprites_wrap = true;
+            cpu.write_bytes(0x310, &[0x01, 0x01]);
+            cpu.execute(0xA310);
+            cpu.execute(0x6000 + w as u16 - 4);
+            cpu.execute(0x6103);
+            cpu.execute(0xD012);
+            assert_eq!(lit(&cpu.display), vec![(3, 3), (3, 4)]);
+            cpu.quirks.sprites_wrap = false;
+            cpu.execute(0xD012);
+            assert_eq!(cpu.v_reg[0xF], 0);
+            assert_eq!(lit(&cpu.display), vec![(3, 3), (3, 4)]);
+        }
+    }
+
     #[test]
     fn ins_skp() {
         let mut cpu = Cpu::new();
@@ -705,6 +1299,11 @@ mod tests {
         assert_eq!(cpu.memory[0x200], 2);
         assert_eq!(cpu.memory[0x201], 5);
         assert_eq!(cpu.memory[0x202], 5);
+
+        // Leading zeros are written too
+        cpu.execute(0x6507);
+        cpu.execute(0xF533);
+        assert_eq!(cpu.memory[0x200..0x203], [0, 0, 7]);
     }
 
     #[test]
@@ -727,6 +1326,13 @@ mod tests {
         assert_eq!(cpu.v_reg[1], 0x13);
         assert_eq!(cpu.v_reg[2], 0x14);
         assert_eq!(cpu.v_reg[3], 0);
+        assert_eq!(cpu.i_reg, 0x200);
+
+        cpu.quirks = Quirks::cosmac_vip();
+        cpu.execute(0xF265);
+        assert_eq!(cpu.i_reg, 0x203);
+        cpu.execute(0xF055);
+        assert_eq!(cpu.i_reg, 0x204);
     }
 
     #[test]
@@ -742,15 +1348,86 @@ mod tests {
         assert!(!cpu.write_bytes(0xFFC, bytes));
     }
 
+    #[test]
+    fn load_rom() {
+        let mut cpu = Cpu::new();
+        let rom = Rom::from_bytes(vec![0x12, 0x34]).unwrap();
+        cpu.load_rom(&rom).unwrap();
+        assert_eq!(cpu.prog_counter, 0x200);
+        assert_eq!(&cpu.memory[0x200..0x202], &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn memory_maps() {
+        let mut cpu = Cpu::with_memory_map(MemoryMap::eti_660()).unwrap();
+        cpu.load_rom(&Rom::from_bytes(vec![0x12, 0x34]).unwrap()).unwrap();
+        assert_eq!(cpu.prog_counter, 0x600);
+        assert_eq!(cpu.memory[0x600], 0x12);
+
+        cpu.v_reg[0] = 2;
+        cpu.execute(0xF029);
+        assert_eq!(cpu.i_reg, 0x50 + 10);
+        assert_eq!(cpu.memory[0x50 + 10], 0xF0);
+
+        let mut cpu = Cpu::with_memory_map(MemoryMap::cosmac_vip()).unwrap();
+        assert_eq!(MemoryMap::cosmac_vip().max_program_size(), 0xEA0 - 0x200);
+        let rom = Rom::from_bytes(vec![0; 0xEA0 - 0x200 + 1]).unwrap();
+        assert!(cpu.load_rom(&rom).is_err());
+    }
+
+    #[test]
+    fn fonts() {
+        let mut cpu = Cpu::with_memory_map(MemoryMap::super_chip()).unwrap();
+        assert!(cpu.load_font(&Font::from_name("vip").unwrap()));
+        cpu.v_reg[0] = 1;
+        cpu.execute(0xF029);
+        assert_eq!(cpu.memory[cpu.i_reg as usize], 0x60);
+        cpu.execute(0xF030);
+        assert_eq!(cpu.i_reg, 0x50 + 80 + 10);
+        assert_eq!(cpu.memory[cpu.i_reg as usize], 0x18);
+    }
+
+    #[test]
+    fn reset() {
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&Rom::from_bytes(vec![0x63, 0x12, 0x22, 0x00]).unwrap()).unwrap();
+        cpu.tick();
+        cpu.tick();
+        cpu.display.pixels[3] = 1;
+        cpu.reset();
+        assert_eq!((cpu.prog_counter, cpu.stack_pointer, cpu.v_reg[3]), (0x200, 0, 0));
+        assert_eq!(cpu.display.pixels[3], 0);
+        assert_eq!(&cpu.memory[0x200..0x204], &[0x63, 0x12, 0x22, 0x00]);
+    }
+
     #[test]
     fn press_key() {
         let mut cpu = Cpu::new();
         assert!(cpu.press_key(0x3));
-        assert_eq!(cpu.keys, 0b0000_0000_0000_0100);
-        assert!(!cpu.press_key(0x0));
+        assert_eq!(cpu.keys, 0b0000_0000_0000_1000);
+        assert!(cpu.press_key(0x0));
+        assert_eq!(cpu.keys, 0b0000_0000_0000_1001);
         assert!(!cpu.press_key(0x10));
     }
 
+    #[test]
+    fn release_key() {
+        let mut cpu = Cpu::new();
+        cpu.press_key(0x3);
+        cpu.press_key(0xF);
+        assert!(cpu.release_key(0x3));
+        assert_eq!(cpu.keys, 0b1000_0000_0000_0000);
+        assert!(!cpu.release_key(0x10));
+    }
+
+    #[test]
+    fn pressed_key_is_seen_by_skp() {
+        let mut cpu = Cpu::new();
+        cpu.press_key(0x0);
+        cpu.execute(0xE09E);
+        assert_eq!(cpu.prog_counter, 2);
+    }
+
     #[test]
     fn tick() {
         let mut cpu = Cpu::new();
@@ -764,4 +1441,46 @@ mod tests {
         cpu.tick();
         assert_eq!(cpu.prog_counter, 0x202);
     }
+
+    #[test]
+    fn frame_counts_down_timers() {
+        let mut cpu = Cpu::new();
+        cpu.prog_counter = 0x200;
+        cpu.write_bytes(0x200, &[0x12, 0x00]); // JP 0x200
+        cpu.delay_timer = 10;
+        cpu.run_frame(DEFAULT_SPEED);
+        assert_eq!(cpu.delay_timer, 9);
+    }
+
+    #[test]
+    fn vip_timing() {
+        let mut cpu = Cpu::new();
+        cpu.timing = Timing::CosmacVip;
+        cpu.prog_counter = 0x200;
+        // ADD V0, 1 then JP 0x200, 158 cycles a loop
+        cpu.write_bytes(0x200, &[0x70, 0x01, 0x12, 0x00]);
+        cpu.run_vip_frame();
+        assert_eq!(cpu.v_reg[0], 17);
+
+        // DRW V0, V0, 1 then ADD V1, 1 then JP 0x200, drawing once a frame
+        let mut cpu = Cpu::new();
+        cpu.timing = Timing::CosmacVip;
+        cpu.prog_counter = 0x200;
+        cpu.write_bytes(0x200, &[0xD0, 0x01, 0x71, 0x01, 0x12, 0x00]);
+        for _ in 0..3 {
+            cpu.run_vip_frame();
+        }
+        assert_eq!(cpu.v_reg[1], 2);
+    }
+
+    #[test]
+    fn tick_timers() {
+        let mut cpu = Cpu::new();
+        cpu.delay_timer = 2;
+        cpu.sound_timer = 1;
+        cpu.tick_timers();
+        assert_eq!((cpu.delay_timer, cpu.sound_timer), (1, 0));
+        cpu.tick_timers();
+        assert_eq!((cpu.delay_timer, cpu.sound_timer), (0, 0));
+    }
 }
@@ -0,0 +1,110 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use utils::get_nth_hex_digit;
+
+/// Turn a two-byte instruction into assembly, using the mnemonics from Cowgod's
+/// CHIP-8 technical reference. Anything that isn't an instruction becomes a `DW`.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(rust_chip8::disasm::disassemble(0x00E0), "CLS");
+/// assert_eq!(rust_chip8::disasm::disassemble(0x6A2F), "LD VA, 0x2F");
+/// assert_eq!(rust_chip8::disasm::disassemble(0xD125), "DRW V1, V2, 5");
+/// ```
+pub fn disassemble(instruction: u16) -> String {
+    let x = get_nth_hex_digit(instruction as u32, 2);
+    let y = get_nth_hex_digit(instruction as u32, 1);
+    let n = get_nth_hex_digit(instruction as u32, 0);
+    let kk = instruction & 0xFF;
+    let nnn = instruction & 0xFFF;
+
+    match instruction >> 12 {
+        0x0 => match instruction {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS 0x{:03X}", nnn)
+        },
+        0x1 => format!("JP 0x{:03X}", nnn),
+        0x2 => format!("CALL 0x{:03X}", nnn),
+        0x3 => format!("SE V{:X}, 0x{:02X}", x, kk),
+        0x4 => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        0x5 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6 => format!("LD V{:X}, 0x{:02X}", x, kk),
+        0x7 => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        0x8 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => format!("DW 0x{:04X}", instruction)
+        },
+        0x9 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA => format!("LD I, 0x{:03X}", nnn),
+        0xB => format!("JP V0, 0x{:03X}", nnn),
+        0xC => format!("RND V{:X}, 0x{:02X}", x, kk),
+        0xD => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE if kk == 0x9E => format!("SKP V{:X}", x),
+        0xE if kk == 0xA1 => format!("SKNP V{:X}", x),
+        0xF => match kk {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x30 => format!("LD HF, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            _ => format!("DW 0x{:04X}", instruction)
+        },
+        _ => format!("DW 0x{:04X}", instruction)
+    }
+}
+
+/// Disassemble a whole program loaded at `start`, two bytes at a time.
+/// Returns the address, raw instruction and assembly for each instruction.
+/// A trailing odd byte becomes a `DB`.
+pub fn disassemble_program(bytes: &[u8], start: u16) -> Vec<(u16, u16, String)> {
+    bytes.chunks(2).enumerate().map(|(i, chunk)| {
+        let addr = start + 2 * i as u16;
+        match chunk.len() {
+            2 => {
+                let instruction = (chunk[0] as u16) << 8 | chunk[1] as u16;
+                (addr, instruction, disassemble(instruction))
+            },
+            _ => (addr, chunk[0] as u16, format!("DB 0x{:02X}", chunk[0]))
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_instructions() {
+        assert_eq!(disassemble(0x5121), "DW 0x5121");
+        assert_eq!(disassemble(0x8128), "DW 0x8128");
+        assert_eq!(disassemble(0xE1FF), "DW 0xE1FF");
+        assert_eq!(disassemble(0xF1FF), "DW 0xF1FF");
+    }
+
+    #[test]
+    fn program() {
+        let listing = disassemble_program(&[0x62, 0x01, 0xF2, 0x1E, 0x12, 0x00, 0xAB], 0x200);
+        assert_eq!(listing, vec![
+            (0x200, 0x6201, "LD V2, 0x01".to_string()),
+            (0x202, 0xF21E, "ADD I, V2".to_string()),
+            (0x204, 0x1200, "JP 0x200".to_string()),
+            (0x206, 0x00AB, "DB 0xAB".to_string())
+        ]);
+    }
+}
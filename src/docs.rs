@@ -0,0 +1,210 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+/// Fields that appear as "Key : Value" lines in the documentation
+const FIELDS: &[&str] = &["title", "author", "genre", "system", "date", "year", "version", "product id"];
+
+/// Most controls documented for one ROM that we keep
+const MAX_CONTROLS: usize = 8;
+
+/// The machine a ROM was written for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum System {
+    Chip8,
+    SuperChip8
+}
+
+impl System {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            System::Chip8 => "CHIP-8",
+            System::SuperChip8 => "SUPER-CHIP"
+        }
+    }
+}
+
+/// The `.txt` file that comes with many ROMs, with the same name as the ROM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomDocs {
+    /// The whole file
+    pub text: String,
+    /// "Key : Value" lines such as Title, Author, System and Date, with keys as they appear in the file
+    pub fields: Vec<(String, String)>,
+    /// Author from either an Author field or a "Title, by: Author" line
+    pub author: Option<String>,
+    /// Sentences that talk about keys or buttons
+    pub controls: Vec<String>,
+    /// Only set if the documentation says which system(s) the ROM is for
+    pub systems: Vec<System>
+}
+
+impl RomDocs {
+    /// Path of the documentation for the ROM at `rom_path`
+    #[cfg(feature = "std")]
+    pub fn path_for(rom_path: &Path) -> PathBuf {
+        rom_path.with_extension("txt")
+    }
+
+    /// Read the documentation next to the ROM at `rom_path`, if there is any
+    #[cfg(feature = "std")]
+    pub fn find(rom_path: &Path) -> Option<RomDocs> {
+        let bytes = fs::read(RomDocs::path_for(rom_path)).ok()?;
+        // Some of the older files aren't valid UTF-8
+        Some(RomDocs::parse(&String::from_utf8_lossy(&bytes)))
+    }
+
+    pub fn parse(text: &str) -> RomDocs {
+        let text = &text.replace("\r\n", "\n");
+        let mut fields = Vec::new();
+        let mut author = None;
+
+        for line in text.lines() {
+            if let Some(colon) = line.find(':') {
+                let key = line[..colon].trim();
+                let value = line[colon + 1..].trim();
+                let lower_key = key.to_lowercase();
+
+                if !value.is_empty() && FIELDS.contains(&lower_key.as_str()) {
+                    if lower_key == "author" {
+                        author = Some(value.to_string());
+                    }
+                    fields.push((key.to_string(), value.to_string()));
+                } else if author.is_none() && lower_key.ends_with(", by") && !value.is_empty() {
+                    // "Blitz, by: David Winter"
+                    author = Some(value.to_string());
+                }
+            }
+        }
+
+        let system_text = fields.iter()
+            .find(|(key, _)| key.to_lowercase() == "system")
+            .map(|(_, value)| value.to_lowercase())
+            .unwrap_or_else(|| text.to_lowercase());
+        let mut systems = Vec::new();
+        let mentions_chip8 = system_text.replace("superchip", "").replace("super-chip", "").contains("chip8")
+            || system_text.contains("chip-8");
+        let mentions_schip = system_text.contains("superchip") || system_text.contains("super-chip")
+            || system_text.contains("schip");
+        if mentions_chip8 {
+            systems.push(System::Chip8);
+        }
+        if mentions_schip {
+            systems.push(System::SuperChip8);
+        }
+
+        RomDocs { text: text.to_string(), fields, author, controls: find_controls(text), systems }
+    }
+
+    /// Value of a field, ignoring case of the key
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.fields.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// True for lines that aren't part of the prose: fields, headings and rules like "-----"
+fn is_break(line: &str) -> bool {
+    let line = line.trim();
+    let is_field = line.find(':').is_some_and(|colon| FIELDS.contains(&line[..colon].trim().to_lowercase().as_str()));
+    line.is_empty() || is_field || line.ends_with(':') || line.chars().all(|c| !c.is_alphanumeric())
+}
+
+/// Split the text into sentences and keep the ones about keys
+fn find_controls(text: &str) -> Vec<String> {
+    let mut controls = Vec::new();
+
+    let mut paragraphs = vec![String::new()];
+    for line in text.lines() {
+        if is_break(line) {
+            paragraphs.push(String::new());
+        } else {
+            let paragraph = paragraphs.last_mut().unwrap();
+            paragraph.push(' ');
+            paragraph.push_str(line);
+        }
+    }
+
+    for paragraph in paragraphs {
+        let paragraph: Vec<&str> = paragraph.split_whitespace().collect();
+        let paragraph = paragraph.join(" ");
+
+        for sentence in paragraph.split_terminator(&['.', '!', '?'][..]) {
+            let sentence = sentence.trim();
+            let lower = sentence.to_lowercase();
+            let about_keys = lower.contains("key") || lower.contains("button") || lower.starts_with("use ")
+                || lower.starts_with("press ");
+
+            if about_keys && !sentence.is_empty() && !controls.iter().any(|c| c == sentence) {
+                controls.push(sentence.to_string());
+                if controls.len() == MAX_CONTROLS {
+                    return controls;
+                }
+            }
+        }
+    }
+
+    controls
+}
+
+/// Guess whether a program needs SUPER-CHIP by looking for instructions that only it has.
+/// Sprite data can look like instructions so this can give false positives.
+pub fn uses_super_chip_instructions(bytes: &[u8]) -> bool {
+    bytes.chunks(2).filter(|c| c.len() == 2).any(|c| {
+        let ins = (c[0] as u16) << 8 | c[1] as u16;
+        match ins {
+            // SCR, SCL, EXIT, LOW, HIGH
+            0x00FB..=0x00FF => true,
+            // SCD n
+            a if a & 0xFFF0 == 0x00C0 => true,
+            // LD HF, Vx; LD R, Vx; LD Vx, R
+            a if a & 0xF0FF == 0xF030 || a & 0xF0FF == 0xF075 || a & 0xF0FF == 0xF085 => true,
+            _ => false
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_value_fields() {
+        let docs = RomDocs::parse("Title\t\t:\tAstro Dodge\nAuthor\t\t:\tMartijn Wenting\n\
+                                   System\t\t:\tChip8 / SuperChip8\nDescription:\n-----\n\
+                                   Button 2,4,6,8 will move your ship, button 5 will start the game.");
+        assert_eq!(docs.field("title"), Some("Astro Dodge"));
+        assert_eq!(docs.author, Some("Martijn Wenting".to_string()));
+        assert_eq!(docs.systems, vec![System::Chip8, System::SuperChip8]);
+        assert_eq!(docs.field("description"), None);
+        assert_eq!(docs.controls, vec!["Button 2,4,6,8 will move your ship, button 5 will start the game"]);
+    }
+
+    #[test]
+    fn by_line_and_controls() {
+        let docs = RomDocs::parse("Blitz, by: David Winter\r\n\r\nThis game is a BOMBER clone. You are in a plane,\r\n\
+                                   and you must destroy the towers. Use 5 to drop a bomb. The game ends.");
+        assert_eq!(docs.author, Some("David Winter".to_string()));
+        assert_eq!(docs.controls, vec!["Use 5 to drop a bomb"]);
+        assert!(docs.systems.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn bundled_docs() {
+        let docs = RomDocs::find(Path::new("examples/games/Pong [Paul Vervalin, 1990].ch8")).unwrap();
+        assert_eq!(docs.controls, vec!["Use keys 7 and 4 move left player and / and * move right player"]);
+        assert!(RomDocs::find(Path::new("examples/games/Airplane.ch8")).is_none());
+    }
+
+    #[test]
+    fn super_chip_instructions() {
+        assert!(!uses_super_chip_instructions(&[0x00, 0xE0, 0x12, 0x00]));
+        assert!(uses_super_chip_instructions(&[0x00, 0xE0, 0x00, 0xFF]));
+        assert!(uses_super_chip_instructions(&[0xF3, 0x75]));
+    }
+}
@@ -0,0 +1,165 @@
+use cpu::Display;
+
+/// How pixels are packed into terminal character cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellMode {
+    /// ▀ ▄ █, one pixel wide and two high
+    HalfBlock,
+    /// Braille patterns, two pixels wide and four high
+    Braille
+}
+
+impl CellMode {
+    pub const NAMES: &'static [&'static str] = &["half", "braille"];
+
+    pub fn from_name(name: &str) -> Option<CellMode> {
+        match name {
+            "half" => Some(CellMode::HalfBlock),
+            "braille" => Some(CellMode::Braille),
+            _ => None
+        }
+    }
+
+    /// Pixels covered by one cell, (width, height)
+    pub fn cell_size(&self) -> (usize, usize) {
+        match *self {
+            CellMode::HalfBlock => (1, 2),
+            CellMode::Braille => (2, 4)
+        }
+    }
+
+    /// Terminal columns and rows needed to show `display`
+    pub fn size(&self, display: &Display) -> (usize, usize) {
+        let (w, h) = self.cell_size();
+        (display.width().div_ceil(w), display.height().div_ceil(h))
+    }
+
+    /// The character for the cell at column `x` and row `y`
+    fn cell(&self, display: &Display, x: usize, y: usize) -> char {
+        let (w, h) = self.cell_size();
+        let lit = |dx: usize, dy: usize| {
+            let (px, py) = (x * w + dx, y * h + dy);
+            px < display.width() && py < display.height() && display.pixel(px, py)
+        };
+
+        match *self {
+            CellMode::HalfBlock => match (lit(0, 0), lit(0, 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' '
+            },
+            CellMode::Braille => {
+                // Bit for each dot, left column then right column, top to bottom
+                const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+                let mut bits = 0;
+                for (dx, column) in DOTS.iter().enumerate() {
+                    for (dy, bit) in column.iter().enumerate() {
+                        if lit(dx, dy) {
+                            bits |= bit;
+                        }
+                    }
+                }
+                if bits == 0 { ' ' } else { ::std::char::from_u32(0x2800 + bits).unwrap() }
+            }
+        }
+    }
+}
+
+/// A run of changed cells on one row, starting at column `x`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellChange {
+    pub x: usize,
+    pub y: usize,
+    pub text: String
+}
+
+/// Turns the display into terminal cells, remembering what was drawn last time so only
+/// the cells that changed need to be written
+pub struct CellRenderer {
+    pub mode: CellMode,
+    // What's on the terminal now, row by row, empty if it needs drawing from scratch
+    drawn: Vec<Vec<char>>
+}
+
+impl CellRenderer {
+    pub fn new(mode: CellMode) -> CellRenderer {
+        CellRenderer { mode, drawn: Vec::new() }
+    }
+
+    /// Forget what's on the terminal, e.g. after it's been cleared, so the next frame is drawn in full
+    pub fn invalidate(&mut self) {
+        self.drawn.clear();
+    }
+
+    /// Cells that differ from the last frame, with neighbouring cells on the same row joined together
+    pub fn changes(&mut self, display: &Display) -> Vec<CellChange> {
+        let (columns, rows) = self.mode.size(display);
+        if self.drawn.len() != rows || self.drawn.first().map_or(0, |row| row.len()) != columns {
+            self.drawn = vec![vec!['\0'; columns]; rows];
+        }
+
+        let mut changes = Vec::new();
+        for y in 0..rows {
+            let mut run: Option<CellChange> = None;
+            for x in 0..columns {
+                let c = self.mode.cell(display, x, y);
+                if self.drawn[y][x] == c {
+                    changes.extend(run.take());
+                    continue;
+                }
+                self.drawn[y][x] = c;
+                run.get_or_insert_with(|| CellChange { x, y, text: String::new() }).text.push(c);
+            }
+            changes.extend(run);
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_blocks() {
+        let mut display = Display::new();
+        display.pixels[0] = 0b11 << 62;
+        display.pixels[1] = 0b101 << 61;
+
+        let mut renderer = CellRenderer::new(CellMode::HalfBlock);
+        let changes = renderer.changes(&display);
+        assert_eq!(changes.len(), 16);
+        assert!(changes[0].text.starts_with("█▀▄ "));
+        assert_eq!(changes[0].text.chars().count(), 64);
+    }
+
+    #[test]
+    fn braille() {
+        let mut display = Display::new();
+        display.pixels[0] = 1 << 63;
+        display.pixels[3] = 1 << 62;
+
+        let mut renderer = CellRenderer::new(CellMode::Braille);
+        assert_eq!(CellMode::Braille.size(&display), (32, 8));
+        let changes = renderer.changes(&display);
+        assert_eq!(changes[0].text.chars().next(), Some('\u{2881}'));
+    }
+
+    #[test]
+    fn only_changes_are_redrawn() {
+        let mut display = Display::new();
+        let mut renderer = CellRenderer::new(CellMode::HalfBlock);
+        renderer.changes(&display);
+        assert_eq!(renderer.changes(&display), vec![]);
+
+        display.pixels[5] = 1 << 60 | 1 << 58 | 1 << 57;
+        assert_eq!(renderer.changes(&display), vec![
+            CellChange { x: 3, y: 2, text: "▄".to_string() },
+            CellChange { x: 5, y: 2, text: "▄▄".to_string() }
+        ]);
+
+        renderer.invalidate();
+        assert_eq!(renderer.changes(&display).len(), 16);
+    }
+}
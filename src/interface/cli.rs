@@ -0,0 +1,857 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::thread;
+#[cfg(feature = "termion")]
+use std::time::Duration;
+
+use analysis::{Analysis, ByteKind};
+use asm;
+use cheats::Cheats;
+use batch::{self, BatchResult};
+use conformance::{self, CHECKS};
+use cpu::{Cpu, MemoryMap, Quirks, DEFAULT_SPEED};
+use font::Font;
+use rom::Rom;
+use timing::Timing;
+use library::Library;
+use interface::Keymap;
+#[cfg(any(feature = "termion", feature = "sdl"))]
+use interface::{self, FileWatch, Frontend, Stop};
+#[cfg(any(feature = "termion", feature = "sdl"))]
+use interface::launcher::Launcher;
+#[cfg(any(feature = "termion", feature = "sdl"))]
+use library::RecentList;
+#[cfg(feature = "termion")]
+use interface::cells::CellMode;
+#[cfg(feature = "termion")]
+use interface::keys::KeyMode;
+#[cfg(feature = "termion")]
+use interface::termion::{self, TermionFrontend, TermionOptions};
+#[cfg(feature = "sdl")]
+use interface::filter::Persistence;
+#[cfg(feature = "sdl")]
+use interface::palette::Palette;
+#[cfg(feature = "sdl")]
+use interface::sdl::{self, Overlay, Scaling, SdlFrontend, SdlOptions};
+
+const USAGE: &str = "\
+rust-chip8, a CHIP-8 emulator
+
+USAGE:
+    rust-chip8 [run] [OPTIONS] [-f] <ROM>
+    rust-chip8 disasm <ROM>
+    rust-chip8 asm <SOURCE> -o <ROM>
+    rust-chip8 info <ROM>
+    rust-chip8 analyze <ROM> [-o <DOT FILE>]
+    rust-chip8 test [OPTIONS] <ROM> [--frames <N>]
+    rust-chip8 test-suite
+    rust-chip8 batch [OPTIONS] <DIR or ROM> [--frames <N>] [--jobs <N>]
+
+COMMANDS:
+    run         Run a ROM (the default), choose one from a list if no ROM is given
+    disasm      Print the instructions in a ROM
+    asm         Assemble a source file, using the syntax printed by disasm
+    info        Print information about a ROM and its documentation
+    analyze     Follow the code from the start of a ROM and report what's code and what's data,
+                optionally writing the control flow graph as Graphviz DOT
+    test        Run a ROM without a frontend and print the screen when it's done
+    test-suite  Check how well each --quirks profile matches the platform it's named after
+    batch       Run every ROM under a directory without a frontend, in parallel, and print a hash of each screen
+
+OPTIONS:
+    -f, --file <ROM>         ROM to load
+        --roms <DIR>         Where to look for ROMs when none is given [default: examples]
+        --frontend <NAME>    termion (default) or sdl
+        --sdl                Same as --frontend sdl
+    -d, --debug              Show registers instead of the screen (termion)
+        --cells <MODE>       Draw pixels with half (default) blocks or braille, 2x4 pixels a character (termion)
+        --keys <MODE>        How to tell when keys are released: auto (default), kitty or hold (termion)
+        --key-hold <MS>      With --keys hold, how long a key stays down after the terminal sends it [default: 500]
+        --quirks <NAME>      Interpreter behaviour: default, vip or schip
+        --compare <NAMES>    Run the ROM with each of a comma separated list of quirks side by side (sdl)
+        --memory <MAP>       Where programs and the font go: default, vip, eti660 (programs at 0x600) or schip
+        --font <FONT>        chip48 (default), vip, dream6800, eti660, schip, octo, or a file with the 80 byte
+                             font, optionally followed by 100 or 160 bytes of big digits
+        --speed <N>          Instructions per second [default: 540]
+        --timing <MODE>      fixed (default), or vip for instructions to take as long as on the COSMAC VIP
+        --seed <N>           Seed for the random number generator
+        --palette <PALETTE>  classic (default), green, amber, lcd or hex colours FG,BG[,PLANE2,BOTH] (sdl)
+        --persistence <MODE> Reduce flicker: off (default), decay or or (sdl)
+        --scale <N>          Size of a CHIP-8 pixel in screen pixels [default: 10] (sdl)
+        --scaling <MODE>     integer (default) or fractional, for when the window is resized (sdl)
+        --fullscreen         Start in fullscreen (sdl)
+        --overlay <NAME>     Draw none (default), scanlines or grid over the display (sdl)
+        --keymap <NAME>      qwerty (default), azerty, or the 16 keys for keypad keys 0-F
+        --frames <N>         Frames to run for with test and batch [default: 60]
+        --jobs <N>           Threads to run batch with [default: one per CPU]
+        --watch              Reload the ROM whenever the file changes
+        --profile <FILE>     Count where the time goes with run and test, and write a report to FILE when done,
+                             and the call stacks to FILE with the extension .folded, for flame graphs
+        --cheats <FILE>      Bytes to freeze or patch in memory, a line each like `freeze 0x2F0 3`
+    -o, --output <FILE>      Output file for asm and analyze
+    -h, --help               Print this message
+
+KEYS:
+    ?  (termion) / F1 (sdl)  Show the ROM's documentation
+    F2 (sdl)                 Switch between the persistence modes
+    F3 (sdl)                 Switch between the overlays
+    /  (termion) / F4 (sdl)  Show memory and the registers, space pauses to edit them
+    \\  (termion) / F7 (sdl)  Show memory as sprites, following I
+    F11 (sdl)                Toggle fullscreen
+    [  (termion) / F5 (sdl)  Reset, starting the program again
+    ]  (termion) / F6 (sdl)  Reload the ROM from disk and start it again
+    ;  (termion) / Esc (sdl) Quit
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrontendKind {
+    Termion,
+    Sdl
+}
+
+impl FrontendKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FrontendKind::Termion => "termion",
+            FrontendKind::Sdl => "sdl"
+        }
+    }
+}
+
+impl Default for FrontendKind {
+    /// The terminal, unless this build only has SDL
+    fn default() -> FrontendKind {
+        if cfg!(feature = "termion") || !cfg!(feature = "sdl") { FrontendKind::Termion } else { FrontendKind::Sdl }
+    }
+}
+
+/// The error for a frontend left out of this build by its cargo feature
+fn frontend_not_built(frontend: FrontendKind) -> CliError {
+    CliError::Failed(format!("this build doesn't include the {} frontend", frontend.name()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Run,
+    Disasm,
+    Asm,
+    Info,
+    Analyze,
+    Test,
+    TestSuite,
+    Batch
+}
+
+/// Everything given on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub file: Option<String>,
+    pub roms: String,
+    pub output: Option<String>,
+    pub frontend: FrontendKind,
+    #[cfg(feature = "termion")]
+    pub termion: TermionOptions,
+    pub quirks: Quirks,
+    /// Names of the quirks to run side by side with --compare, empty for just `quirks`
+    pub compare: Vec<String>,
+    pub memory_map: MemoryMap,
+    pub font: Font,
+    pub speed: u32,
+    pub timing: Timing,
+    pub seed: Option<u64>,
+    #[cfg(feature = "sdl")]
+    pub sdl: SdlOptions,
+    pub keymap: Keymap,
+    pub frames: u32,
+    /// Threads for batch, None for one per CPU
+    pub jobs: Option<usize>,
+    /// Reload the ROM when the file changes
+    pub watch: bool,
+    /// Where to write the profile report
+    pub profile: Option<String>,
+    pub cheats: Option<Cheats>
+}
+
+impl Options {
+    fn new() -> Options {
+        Options {
+            command: Command::Run, file: None, roms: "examples".to_string(), output: None,
+            frontend: FrontendKind::default(), #[cfg(feature = "termion")] termion: TermionOptions::new(),
+            quirks: Quirks::new(), compare: Vec::new(), memory_map: MemoryMap::new(), font: Font::new(),
+            speed: DEFAULT_SPEED, timing: Timing::Fixed, seed: None, #[cfg(feature = "sdl")] sdl: SdlOptions::new(),
+            keymap: Keymap::qwerty(), frames: 60, jobs: None, watch: false, profile: None, cheats: None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CliError {
+    /// `--help` was given
+    Help,
+    /// The arguments don't make sense
+    Usage(String),
+    /// The arguments were fine but the command failed
+    Failed(String)
+}
+
+impl CliError {
+    /// Exit code for the process
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            CliError::Help => 0,
+            CliError::Failed(_) => 1,
+            CliError::Usage(_) => 2
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::Usage(ref message) => write!(f, "error: {}\n\nFor more information try --help", message),
+            CliError::Failed(ref message) => write!(f, "error: {}", message)
+        }
+    }
+}
+
+fn parse_number<T: ::std::str::FromStr>(option: &str, value: &str) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::Usage(format!("invalid number '{}' for {}", value, option)))
+}
+
+/// Parse the arguments, not including the program name
+pub fn parse_args(args: &[String]) -> Result<Options, CliError> {
+    let mut options = Options::new();
+    let mut args = args.iter().peekable();
+
+    if let Some(command) = args.peek().map(|s| s.as_str()) {
+        let command = match command {
+            "run" => Some(Command::Run),
+            "disasm" => Some(Command::Disasm),
+            "asm" => Some(Command::Asm),
+            "info" => Some(Command::Info),
+            "analyze" => Some(Command::Analyze),
+            "test" => Some(Command::Test),
+            "test-suite" => Some(Command::TestSuite),
+            "batch" => Some(Command::Batch),
+            _ => None
+        };
+        if let Some(command) = command {
+            options.command = command;
+            args.next();
+        }
+    }
+
+    while let Some(arg) = args.next() {
+        let arg = arg.as_str();
+
+        let mut value = |option: &str| match args.next() {
+            Some(value) => Ok(value.clone()),
+            None => Err(CliError::Usage(format!("{} needs a value", option)))
+        };
+
+        match arg {
+            "-h" | "--help" => return Err(CliError::Help),
+            #[cfg(feature = "termion")]
+            "-d" | "--debug" => options.termion.debug = true,
+            "--sdl" => options.frontend = FrontendKind::Sdl,
+            "-f" | "--file" => options.file = Some(value(arg)?),
+            "-o" | "--output" => options.output = Some(value(arg)?),
+            "--frontend" => {
+                options.frontend = match value(arg)?.as_str() {
+                    "termion" => FrontendKind::Termion,
+                    "sdl" => FrontendKind::Sdl,
+                    other => return Err(CliError::Usage(format!("unknown frontend '{}', expected termion or sdl", other)))
+                }
+            },
+            "--quirks" => {
+                let name = value(arg)?;
+                options.quirks = Quirks::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown quirks '{}', expected one of {}", name, Quirks::NAMES.join(", "))))?;
+            },
+            "--speed" => {
+                options.speed = parse_number(arg, &value(arg)?)?;
+                if options.speed == 0 {
+                    return Err(CliError::Usage("--speed must be more than 0".to_string()));
+                }
+            },
+            "--compare" => {
+                options.compare = value(arg)?.split(',').map(|name| name.trim().to_string()).collect();
+                if let Some(name) = options.compare.iter().find(|name| Quirks::from_name(name).is_none()) {
+                    return Err(CliError::Usage(
+                        format!("unknown quirks '{}', expected one of {}", name, Quirks::NAMES.join(", "))));
+                }
+            },
+            "--jobs" => options.jobs = Some(parse_number(arg, &value(arg)?)?),
+            "--watch" => options.watch = true,
+            "--profile" => options.profile = Some(value(arg)?),
+            "--cheats" => {
+                let path = value(arg)?;
+                let source = String::from_utf8_lossy(&read_file(&path)?).into_owned();
+                options.cheats = Some(Cheats::parse(&source)
+                    .map_err(|e| CliError::Failed(format!("could not read cheats from '{}': {}", path, e)))?);
+            },
+            "--memory" => {
+                let name = value(arg)?;
+                options.memory_map = MemoryMap::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown memory map '{}', expected {}", name, MemoryMap::NAMES.join(", "))))?;
+            },
+            "--font" => {
+                let font = value(arg)?;
+                options.font = match Font::from_name(&font) {
+                    Some(font) => font,
+                    None => Font::from_bytes(&read_file(&font)?).ok_or_else(|| CliError::Failed(
+                        format!("'{}' is not a font, expected 80, 180 or 240 bytes", font)))?
+                };
+            },
+            "--timing" => {
+                let name = value(arg)?;
+                options.timing = Timing::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown timing '{}', expected {}", name, Timing::NAMES.join(", "))))?;
+            },
+            "--seed" => options.seed = Some(parse_number(arg, &value(arg)?)?),
+            #[cfg(feature = "sdl")]
+            "--persistence" => {
+                let name = value(arg)?;
+                options.sdl.persistence = Persistence::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown persistence mode '{}', expected {}", name, Persistence::NAMES.join(", "))))?;
+            },
+            #[cfg(feature = "sdl")]
+            "--palette" => {
+                let palette = value(arg)?;
+                options.sdl.palette = Palette::parse(&palette).ok_or_else(|| CliError::Usage(
+                    format!("invalid palette '{}', expected {} or hex colours like ffffff,000000",
+                            palette, Palette::NAMES.join(", "))))?;
+            },
+            #[cfg(feature = "sdl")]
+            "--scale" => {
+                options.sdl.scale = parse_number(arg, &value(arg)?)?;
+                if options.sdl.scale == 0 {
+                    return Err(CliError::Usage("--scale must be more than 0".to_string()));
+                }
+            },
+            #[cfg(feature = "sdl")]
+            "--scaling" => {
+                let name = value(arg)?;
+                options.sdl.scaling = Scaling::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown scaling '{}', expected integer or fractional", name)))?;
+            },
+            #[cfg(feature = "termion")]
+            "--cells" => {
+                let name = value(arg)?;
+                options.termion.cells = CellMode::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown cell mode '{}', expected {}", name, CellMode::NAMES.join(", "))))?;
+            },
+            #[cfg(feature = "termion")]
+            "--keys" => {
+                let name = value(arg)?;
+                options.termion.keys = KeyMode::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown key mode '{}', expected {}", name, KeyMode::NAMES.join(", "))))?;
+            },
+            #[cfg(feature = "termion")]
+            "--key-hold" => options.termion.hold_delay = Duration::from_millis(parse_number(arg, &value(arg)?)?),
+            #[cfg(feature = "sdl")]
+            "--fullscreen" => options.sdl.fullscreen = true,
+            #[cfg(feature = "sdl")]
+            "--overlay" => {
+                let name = value(arg)?;
+                options.sdl.overlay = Overlay::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown overlay '{}', expected {}", name, Overlay::NAMES.join(", "))))?;
+            },
+            "--keymap" => {
+                let name = value(arg)?;
+                options.keymap = Keymap::from_name(&name).ok_or_else(|| CliError::Usage(
+                    format!("unknown keymap '{}', expected {} or 16 keys", name, Keymap::NAMES.join(", "))))?;
+            },
+            "--roms" => options.roms = value(arg)?,
+            "--frames" => options.frames = parse_number(arg, &value(arg)?)?,
+            #[cfg(not(feature = "termion"))]
+            "-d" | "--debug" | "--cells" | "--keys" | "--key-hold" => {
+                return Err(CliError::Usage(format!("{} is for the termion frontend, which this build doesn't include", arg)));
+            },
+            #[cfg(not(feature = "sdl"))]
+            "--persistence" | "--palette" | "--scale" | "--scaling" | "--fullscreen" | "--overlay" => {
+                return Err(CliError::Usage(format!("{} is for the sdl frontend, which this build doesn't include", arg)));
+            },
+            a if a.starts_with('-') => return Err(CliError::Usage(format!("unknown option '{}'", a))),
+            a => {
+                if options.file.is_some() {
+                    return Err(CliError::Usage(format!("unexpected argument '{}'", a)));
+                }
+                options.file = Some(a.to_string());
+            }
+        }
+    }
+
+    let needs_file = options.command != Command::Run && options.command != Command::TestSuite;
+    if needs_file && options.file.is_none() {
+        return Err(CliError::Usage("no file given".to_string()));
+    }
+    if !options.compare.is_empty() && options.frontend != FrontendKind::Sdl {
+        return Err(CliError::Usage("--compare needs the sdl frontend".to_string()));
+    }
+    if options.command == Command::Asm && options.output.is_none() {
+        return Err(CliError::Usage("asm needs an output file, given with -o".to_string()));
+    }
+
+    Ok(options)
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, CliError> {
+    fs::read(path).map_err(|e| CliError::Failed(format!("could not read '{}': {}", path, e)))
+}
+
+fn read_rom(path: &str) -> Result<Rom, CliError> {
+    Rom::from_path(path).map_err(|e| CliError::Failed(format!("could not load '{}': {}", path, e)))
+}
+
+fn load_cpu(options: &Options, rom: &Rom) -> Result<Cpu, CliError> {
+    let mut cpu = Cpu::with_memory_map(options.memory_map.clone()).map_err(|e| CliError::Failed(e.to_string()))?;
+    cpu.quirks = options.quirks;
+    cpu.timing = options.timing;
+    if !cpu.load_font(&options.font) {
+        return Err(CliError::Failed("the font doesn't fit in memory".to_string()));
+    }
+    if let Some(seed) = options.seed {
+        cpu.seed(seed);
+    }
+    if options.profile.is_some() {
+        cpu.start_profiling();
+    }
+    cpu.load_rom(rom).map_err(|e| CliError::Failed(format!("could not load '{}': {}", rom.title, e)))?;
+    if let Some(ref cheats) = options.cheats {
+        cpu.set_cheats(cheats.clone());
+    }
+    Ok(cpu)
+}
+
+#[cfg(any(feature = "termion", feature = "sdl"))]
+/// Show the launcher for the ROMs in `options.roms`, returns the path of the chosen ROM
+fn choose_rom(options: &Options, recent: &RecentList) -> Result<Option<String>, CliError> {
+    let library = Library::scan(&options.roms)
+        .map_err(|e| CliError::Failed(format!("could not read '{}': {}", options.roms, e)))?;
+    if library.entries.is_empty() {
+        return Err(CliError::Failed(format!("no ROMs found in '{}', give one with -f or --roms", options.roms)));
+    }
+
+    let mut launcher = Launcher::new(library, recent);
+    let chosen = match options.frontend {
+        #[cfg(feature = "termion")]
+        FrontendKind::Termion => termion::choose_rom(&mut launcher).map_err(CliError::Failed)?,
+        #[cfg(feature = "sdl")]
+        FrontendKind::Sdl => sdl::choose_rom(&mut launcher, &options.sdl).map_err(CliError::Failed)?,
+        #[allow(unreachable_patterns)]
+        frontend => return Err(frontend_not_built(frontend))
+    };
+    Ok(chosen.map(|path| path.to_string_lossy().into_owned()))
+}
+
+#[cfg(any(feature = "termion", feature = "sdl"))]
+fn run(options: &Options) -> Result<(), CliError> {
+    let recent_path = RecentList::default_path();
+    let mut recent = recent_path.as_ref().map(|path| RecentList::load(path)).unwrap_or_default();
+
+    let path = match options.file {
+        Some(ref path) => path.clone(),
+        None => match choose_rom(options, &recent)? {
+            Some(path) => path,
+            None => return Ok(())
+        }
+    };
+    let rom = read_rom(&path)?;
+    let cpus = load_cpus(options, &rom)?;
+
+    // Not being able to save the list shouldn't stop the game from running
+    recent.add(Path::new(&path));
+    if let Some(ref recent_path) = recent_path {
+        let _ = recent.save(recent_path);
+    }
+
+    let cpus = match options.frontend {
+        #[cfg(feature = "termion")]
+        FrontendKind::Termion => {
+            let mut frontend = TermionFrontend::new(&rom, options.keymap.clone(), &options.termion)
+                .map_err(CliError::Failed)?;
+            play(&mut frontend, options, &path, cpus)
+        },
+        #[cfg(feature = "sdl")]
+        FrontendKind::Sdl => {
+            let mut frontend = SdlFrontend::new(&rom, options.keymap.clone(), &options.sdl)
+                .map_err(CliError::Failed)?;
+            if !options.compare.is_empty() {
+                frontend.tile(options.compare.clone());
+            }
+            play(&mut frontend, options, &path, cpus)
+        },
+        #[allow(unreachable_patterns)]
+        frontend => return Err(frontend_not_built(frontend))
+    };
+    // With --compare only the first machine is profiled
+    match options.profile {
+        Some(ref profile) => write_profile(&cpus[0], profile),
+        None => Ok(())
+    }
+}
+
+#[cfg(any(feature = "termion", feature = "sdl"))]
+/// One machine, or one for each of the --compare quirks
+fn load_cpus(options: &Options, rom: &Rom) -> Result<Vec<Cpu>, CliError> {
+    if options.compare.is_empty() {
+        return Ok(vec![load_cpu(options, rom)?]);
+    }
+    // The same seed for every machine, so any difference comes from the quirks
+    let seed = options.seed.unwrap_or_else(::rand::random);
+    options.compare.iter().map(|name| {
+        let mut options = options.clone();
+        options.quirks = Quirks::from_name(name).unwrap();
+        options.seed = Some(seed);
+        load_cpu(&options, rom)
+    }).collect()
+}
+
+#[cfg(any(feature = "termion", feature = "sdl"))]
+/// Run `cpus` in `frontend` until the user quits, loading the ROM at `path` again when asked to.
+/// Returns the machines as they were at the end.
+fn play<F: Frontend>(frontend: &mut F, options: &Options, path: &str, mut cpus: Vec<Cpu>) -> Vec<Cpu> {
+    let mut watch = if options.watch { Some(FileWatch::new(path)) } else { None };
+    while interface::run_many(frontend, &mut cpus, options.speed, watch.as_mut()) == Stop::Reload {
+        // The file might be half written, so keep the old program running until it loads.
+        // It'll be reloaded again once it's finished changing.
+        if let Ok(reloaded) = read_rom(path).and_then(|rom| load_cpus(options, &rom)) {
+            cpus = reloaded;
+        }
+    }
+    cpus
+}
+
+/// Write the report for a cpu from `load_cpu` with --profile to `path`, and the call stacks next to it
+fn write_profile(cpu: &Cpu, path: &str) -> Result<(), CliError> {
+    let profile = cpu.profile().unwrap();
+    let folded = Path::new(path).with_extension("folded");
+    fs::write(path, profile.report(&cpu.memory))
+        .map_err(|e| CliError::Failed(format!("could not write '{}': {}", path, e)))?;
+    fs::write(&folded, profile.folded_stacks())
+        .map_err(|e| CliError::Failed(format!("could not write '{}': {}", folded.display(), e)))
+}
+
+fn disasm(path: &str, start: u16) -> Result<(), CliError> {
+    let rom = read_rom(path)?;
+    for (addr, instruction, text) in Analysis::new(&rom.bytes, start).listing() {
+        println!("{:03X}: {:04X}  {}", addr, instruction, text);
+    }
+    Ok(())
+}
+
+fn assemble(path: &str, output: &str, start: u16) -> Result<(), CliError> {
+    let source = String::from_utf8(read_file(path)?)
+        .map_err(|_| CliError::Failed(format!("'{}' is not valid UTF-8", path)))?;
+    let bytes = asm::assemble(&source, start).map_err(|e| CliError::Failed(format!("{}: {}", path, e)))?;
+    fs::write(output, &bytes).map_err(|e| CliError::Failed(format!("could not write '{}': {}", output, e)))?;
+    Ok(())
+}
+
+/// "0x200-0x213, 0x2F0-0x2FF", or "none"
+fn format_ranges(ranges: &[Range<u16>]) -> String {
+    if ranges.is_empty() {
+        return "none".to_string();
+    }
+    ranges.iter().map(|range| format!("0x{:03X}-0x{:03X}", range.start, range.end - 1)).collect::<Vec<_>>().join(", ")
+}
+
+fn analyze(path: &str, output: Option<&str>, start: u16) -> Result<(), CliError> {
+    let rom = read_rom(path)?;
+    let analysis = Analysis::new(&rom.bytes, start);
+
+    let size = |kind| analysis.kinds.iter().filter(|&&k| k == kind).count();
+    println!("Code:           {} bytes in {} blocks, {}", size(ByteKind::Code), analysis.blocks.len(),
+             format_ranges(&analysis.ranges(ByteKind::Code)));
+    println!("Sprites:        {} bytes, {}", size(ByteKind::Sprite), format_ranges(&analysis.ranges(ByteKind::Sprite)));
+    println!("Data:           {} bytes, {}", size(ByteKind::Data), format_ranges(&analysis.ranges(ByteKind::Data)));
+    println!("Not reached:    {} bytes, {}", size(ByteKind::Unknown),
+             format_ranges(&analysis.ranges(ByteKind::Unknown)));
+
+    let graph = analysis.call_graph();
+    let subroutines: Vec<String> = analysis.subroutines.iter().map(|&entry| {
+        let callers: Vec<String> = graph.iter().filter(|&(_, callees)| callees.contains(&entry))
+            .map(|(caller, _)| format!("0x{:03X}", caller)).collect();
+        format!("0x{:03X} (from {})", entry, callers.join(", "))
+    }).collect();
+    let addresses = |addresses: &BTreeSet<u16>| if addresses.is_empty() {
+        "none".to_string()
+    } else {
+        addresses.iter().map(|addr| format!("0x{:03X}", addr)).collect::<Vec<_>>().join(", ")
+    };
+    println!("Subroutines:    {}", if subroutines.is_empty() { "none".to_string() } else { subroutines.join(", ") });
+    println!("Computed jumps: {}", addresses(&analysis.computed_jumps));
+    println!("Writes to code: {}", addresses(&analysis.self_modifying));
+
+    if let Some(output) = output {
+        fs::write(output, analysis.to_dot())
+            .map_err(|e| CliError::Failed(format!("could not write '{}': {}", output, e)))?;
+    }
+    Ok(())
+}
+
+fn info(path: &str, memory_map: &MemoryMap) -> Result<(), CliError> {
+    let rom = read_rom(path)?;
+    println!("File:   {}", path);
+    println!("Title:  {}", rom.title);
+    println!("Author: {}", rom.author.as_deref().unwrap_or("unknown"));
+    println!("Year:   {}", rom.year.as_deref().unwrap_or("unknown"));
+    println!("Size:   {} bytes", rom.bytes.len());
+    match memory_map.max_program_size().checked_sub(rom.bytes.len()) {
+        Some(free) => println!("Free:   {} bytes", free),
+        None => println!("Free:   none, too large by {} bytes", rom.bytes.len() - memory_map.max_program_size())
+    }
+    println!("SHA-1:  {}", rom.hash);
+    println!("System: {}", rom.system().name());
+
+    if let Some(ref docs) = rom.docs {
+        println!();
+        for (key, value) in &docs.fields {
+            println!("{}: {}", key, value);
+        }
+        if !docs.controls.is_empty() {
+            println!("Controls:");
+            for control in &docs.controls {
+                println!("    {}", control);
+            }
+        }
+    } else {
+        println!("Docs:   none");
+    }
+    Ok(())
+}
+
+fn test(options: &Options) -> Result<(), CliError> {
+    let rom = read_rom(options.file.as_ref().unwrap())?;
+    let mut cpu = load_cpu(options, &rom)?;
+
+    for _ in 0..options.frames {
+        cpu.run_frame(options.speed);
+    }
+
+    for row in cpu.display.pixels.iter() {
+        let line: String = (0..cpu.display.width()).rev().map(|i| if row >> i & 1 == 1 { '#' } else { '.' }).collect();
+        println!("{}", line);
+    }
+    if let Some(ref profile) = options.profile {
+        write_profile(&cpu, profile)?;
+    }
+    match cpu.fault() {
+        Some(fault) => Err(CliError::Failed(format!("stopped at {:#05X}: {}", cpu.prog_counter, fault))),
+        None => Ok(())
+    }
+}
+
+/// Run every conformance check with every quirks profile and print a table of the results
+fn test_suite() -> Result<(), CliError> {
+    print!("{:<7} {:<8} {:<52}", "Opcode", "Category", "Check");
+    for name in Quirks::NAMES {
+        print!(" {:<7}", name);
+    }
+    println!();
+
+    let mut failures = Vec::new();
+    for check in CHECKS {
+        print!("{:<7} {:<8} {:<52}", check.opcode, check.category.name(), check.description);
+        for &name in Quirks::NAMES {
+            match conformance::run_check(check, name) {
+                Ok(()) => print!(" {:<7}", "pass"),
+                Err(e) => {
+                    print!(" {:<7}", "FAIL");
+                    failures.push(format!("{} {} ({}): {}", check.opcode, check.description, name, e));
+                }
+            }
+        }
+        println!();
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+    println!();
+    for failure in &failures {
+        println!("{}", failure);
+    }
+    Err(CliError::Failed(format!("{} checks failed", failures.len())))
+}
+
+/// Run every ROM given by `options.file`, a ROM or a directory of them, and print the hash of the
+/// screen each one ends up with, so the output can be saved and compared against later runs
+fn run_batch(options: &Options) -> Result<(), CliError> {
+    let file = options.file.as_ref().unwrap();
+    let paths: Vec<PathBuf> = if Path::new(file).is_dir() {
+        let library = Library::scan(file).map_err(|e| CliError::Failed(format!("could not read '{}': {}", file, e)))?;
+        library.entries.into_iter().filter_map(|entry| entry.rom.path).collect()
+    } else {
+        vec![PathBuf::from(file)]
+    };
+
+    let jobs = options.jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    // Without a seed given, use the same one every time so the results can be compared
+    let options = Options { seed: Some(options.seed.unwrap_or(0)), ..options.clone() };
+    let results = batch::run_batch(&paths, jobs, options.frames, options.speed, |rom| {
+        load_cpu(&options, rom).map_err(|e| match e {
+            CliError::Failed(message) => message,
+            e => e.to_string()
+        })
+    });
+
+    let mut failed = 0;
+    for BatchResult { path, outcome } in results {
+        match outcome {
+            Ok(cpu) => println!("{:016x}  {}", batch::screen_hash(&cpu.display), path.display()),
+            Err(e) => {
+                println!("{:<16}  {}: {}", "error", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(CliError::Failed(format!("{} ROMs could not be run", failed)));
+    }
+    Ok(())
+}
+
+/// Run the command given by `args`, the first of which is the program name.
+/// Returns the exit code for the process.
+pub fn parse_args_and_run(args: Vec<String>) -> i32 {
+    let result = parse_args(&args[1..]).and_then(|options| {
+        match options.command {
+            #[cfg(any(feature = "termion", feature = "sdl"))]
+            Command::Run => run(&options),
+            #[cfg(not(any(feature = "termion", feature = "sdl")))]
+            Command::Run => Err(frontend_not_built(options.frontend)),
+            Command::Disasm => disasm(options.file.as_ref().unwrap(), options.memory_map.program_start),
+            Command::Asm => assemble(options.file.as_ref().unwrap(), options.output.as_ref().unwrap(),
+                                     options.memory_map.program_start),
+            Command::Info => info(options.file.as_ref().unwrap(), &options.memory_map),
+            Command::Analyze => analyze(options.file.as_ref().unwrap(), options.output.as_deref(),
+                                        options.memory_map.program_start),
+            Command::Test => test(&options),
+            Command::TestSuite => test_suite(),
+            Command::Batch => run_batch(&options)
+        }
+    });
+
+    match result {
+        Ok(()) => 0,
+        Err(CliError::Help) => {
+            print!("{}", CliError::Help);
+            0
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, CliError> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse("").unwrap();
+        assert_eq!(options, Options::new());
+        assert_eq!(parse("-f game.ch8").unwrap().file, Some("game.ch8".to_string()));
+        assert_eq!(parse("game.ch8").unwrap().file, Some("game.ch8".to_string()));
+    }
+
+    #[test]
+    #[cfg(feature = "termion")]
+    fn old_style_flags() {
+        let options = parse("-d -f game.ch8 --sdl").unwrap();
+        assert!(options.termion.debug);
+        assert_eq!(options.frontend, FrontendKind::Sdl);
+        assert_eq!(options.file, Some("game.ch8".to_string()));
+    }
+
+    #[test]
+    fn commands_and_options() {
+        let options = parse("test --quirks vip --speed 1000 --seed 42 --frames 5 --keymap azerty rom.ch8").unwrap();
+        assert_eq!(options.command, Command::Test);
+        assert_eq!(options.quirks, Quirks::cosmac_vip());
+        assert_eq!(options.speed, 1000);
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.frames, 5);
+        assert_eq!(options.keymap, Keymap::azerty());
+        assert_eq!(parse("--timing vip").unwrap().timing, Timing::CosmacVip);
+        assert_eq!(parse("--memory eti660").unwrap().memory_map, MemoryMap::eti_660());
+        assert_eq!(parse("--font octo").unwrap().font, Font::from_name("octo").unwrap());
+        assert_eq!(parse("test-suite").unwrap().command, Command::TestSuite);
+        let options = parse("batch --jobs 3 examples").unwrap();
+        assert_eq!((options.command, options.jobs), (Command::Batch, Some(3)));
+        assert_eq!(parse("--sdl --compare default,vip").unwrap().compare, vec!["default", "vip"]);
+        assert!(parse("--watch game.ch8").unwrap().watch);
+        assert_eq!(parse("test --profile out.txt rom.ch8").unwrap().profile, Some("out.txt".to_string()));
+    }
+
+    #[test]
+    #[cfg(feature = "termion")]
+    fn termion_options() {
+        assert_eq!(parse("--cells braille").unwrap().termion.cells, CellMode::Braille);
+        let options = parse("--keys hold --key-hold 300").unwrap();
+        assert_eq!(options.termion.keys, KeyMode::Hold);
+        assert_eq!(options.termion.hold_delay, Duration::from_millis(300));
+    }
+
+    #[test]
+    #[cfg(feature = "sdl")]
+    fn sdl_options() {
+        let options = parse("run --frontend sdl --scale 4 --palette 33ff66,000000 --fullscreen rom.ch8").unwrap();
+        assert_eq!(options.frontend, FrontendKind::Sdl);
+        assert_eq!(options.sdl.scale, 4);
+        assert!(options.sdl.fullscreen);
+        assert_eq!(options.sdl.palette.foreground, ::sdl2::pixels::Color::RGB(0x33, 0xFF, 0x66));
+        assert_eq!(parse("--palette amber").unwrap().sdl.palette, Palette::from_name("amber").unwrap());
+        assert_eq!(parse("--scaling fractional").unwrap().sdl.scaling, Scaling::Fractional);
+        assert_eq!(parse("--overlay grid").unwrap().sdl.overlay, Overlay::Grid);
+        assert_eq!(parse("--persistence decay").unwrap().sdl.persistence, Persistence::Decay);
+        assert_eq!(parse("--persistence glow").unwrap_err().exit_code(), 2);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("--help"), Err(CliError::Help));
+        assert_eq!(parse("--bogus").unwrap_err().exit_code(), 2);
+        assert_eq!(parse("-f").unwrap_err().exit_code(), 2);
+        assert_eq!(parse("--speed fast").unwrap_err().exit_code(), 2);
+        assert_eq!(parse("--quirks nope").unwrap_err().exit_code(), 2);
+        assert_eq!(parse("a.ch8 b.ch8").unwrap_err().exit_code(), 2);
+        assert_eq!(parse("disasm").unwrap_err().exit_code(), 2);
+        assert_eq!(parse("asm source.asm").unwrap_err().exit_code(), 2);
+        assert_eq!(parse("--sdl --compare vip,nope").unwrap_err().exit_code(), 2);
+        assert_eq!(parse("--compare vip").unwrap_err().exit_code(), 2);
+    }
+
+    #[test]
+    fn cheats_file() {
+        let path = ::std::env::temp_dir().join(format!("rust-chip8-cheats-test-{}", ::std::process::id()));
+        fs::write(&path, "freeze 0x2F0 3\n").unwrap();
+        let options = parse(&format!("--cheats {} game.ch8", path.display())).unwrap();
+        assert_eq!(options.cheats.unwrap().cheats.len(), 1);
+
+        fs::write(&path, "freeze 0x2F0\n").unwrap();
+        assert_eq!(parse(&format!("--cheats {} game.ch8", path.display())).unwrap_err().to_string(),
+                   format!("error: could not read cheats from '{}': line 1: missing bytes to write", path.display()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_rom() {
+        assert_eq!(read_rom("does/not/exist.ch8").unwrap_err().exit_code(), 1);
+    }
+}
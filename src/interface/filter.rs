@@ -0,0 +1,156 @@
+use cpu::Display;
+
+/// How much of a pixel's brightness is left after each frame once it's turned off
+const DECAY: f32 = 0.6;
+/// Pixels dimmer than this are drawn as background
+const CUTOFF: f32 = 0.05;
+
+/// Ways of hiding the flicker caused by games erasing and redrawing sprites
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Persistence {
+    /// Show each frame as it is
+    Off,
+    /// Pixels fade out over a few frames like the phosphor on an old screen
+    Decay,
+    /// A pixel is lit if it was lit in this frame or the one before
+    OrLastTwo
+}
+
+impl Persistence {
+    pub const NAMES: &'static [&'static str] = &["off", "decay", "or"];
+
+    pub fn from_name(name: &str) -> Option<Persistence> {
+        match name {
+            "off" => Some(Persistence::Off),
+            "decay" => Some(Persistence::Decay),
+            "or" => Some(Persistence::OrLastTwo),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Persistence::Off => "off",
+            Persistence::Decay => "decay",
+            Persistence::OrLastTwo => "or"
+        }
+    }
+
+    /// The mode after this one, for cycling through them with a hotkey
+    pub fn next(&self) -> Persistence {
+        match *self {
+            Persistence::Off => Persistence::Decay,
+            Persistence::Decay => Persistence::OrLastTwo,
+            Persistence::OrLastTwo => Persistence::Off
+        }
+    }
+}
+
+/// Turns the display into a brightness from 0.0 to 1.0 for every pixel, remembering
+/// earlier frames as needed by the persistence mode
+pub struct PersistenceFilter {
+    pub mode: Persistence,
+    // Sized for the display last given to `apply`
+    width: usize,
+    brightness: Vec<f32>,
+    previous: Vec<u128>
+}
+
+impl PersistenceFilter {
+    pub fn new(mode: Persistence) -> PersistenceFilter {
+        PersistenceFilter { mode, width: 0, brightness: Vec::new(), previous: Vec::new() }
+    }
+
+    /// Update with the next frame. Should be called once per frame, returns the brightness
+    /// of each pixel row by row.
+    pub fn apply(&mut self, display: &Display) -> &[f32] {
+        let (width, height) = (display.width(), display.height());
+        // Earlier frames at another size have nothing to do with this one
+        if width != self.width || height != self.previous.len() {
+            self.width = width;
+            self.brightness = vec![0.0; width * height];
+            self.previous = vec![0; height];
+        }
+
+        for (y, &row) in display.pixels.iter().enumerate() {
+            let shown = match self.mode {
+                Persistence::OrLastTwo => row | self.previous[y],
+                _ => row
+            };
+
+            for x in 0..width {
+                let lit = shown >> (width - 1 - x) & 1 == 1;
+                let brightness = &mut self.brightness[y * width + x];
+                *brightness = match self.mode {
+                    _ if lit => 1.0,
+                    Persistence::Decay if *brightness * DECAY >= CUTOFF => *brightness * DECAY,
+                    _ => 0.0
+                };
+            }
+        }
+        self.previous.clone_from(&display.pixels);
+
+        &self.brightness
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display_with(x: usize, y: usize) -> Display {
+        let mut display = Display::new();
+        display.pixels[y] = 1 << (63 - x);
+        display
+    }
+
+    #[test]
+    fn off_shows_current_frame() {
+        let mut filter = PersistenceFilter::new(Persistence::Off);
+        assert_eq!(filter.apply(&display_with(3, 2))[2 * 64 + 3], 1.0);
+        assert_eq!(filter.apply(&Display::new())[2 * 64 + 3], 0.0);
+    }
+
+    #[test]
+    fn decay_fades_out() {
+        let mut filter = PersistenceFilter::new(Persistence::Decay);
+        filter.apply(&display_with(0, 0));
+        assert_eq!(filter.apply(&Display::new())[0], DECAY);
+        assert_eq!(filter.apply(&Display::new())[0], DECAY * DECAY);
+        for _ in 0..10 {
+            filter.apply(&Display::new());
+        }
+        assert_eq!(filter.apply(&Display::new())[0], 0.0);
+    }
+
+    #[test]
+    fn or_keeps_last_frame() {
+        let mut filter = PersistenceFilter::new(Persistence::OrLastTwo);
+        filter.apply(&display_with(63, 31));
+        assert_eq!(filter.apply(&Display::new())[31 * 64 + 63], 1.0);
+        assert_eq!(filter.apply(&Display::new())[31 * 64 + 63], 0.0);
+    }
+
+    #[test]
+    fn hires() {
+        let mut filter = PersistenceFilter::new(Persistence::OrLastTwo);
+        filter.apply(&display_with(0, 0));
+        let mut display = Display::with_size(128, 64).unwrap();
+        display.pixels[63] = 1;
+        // The pixel from the 64x32 frame isn't carried over
+        let brightness = filter.apply(&display);
+        assert_eq!(brightness.len(), 128 * 64);
+        assert_eq!(brightness.iter().filter(|&&b| b > 0.0).count(), 1);
+        assert_eq!(brightness[63 * 128 + 127], 1.0);
+        assert_eq!(filter.apply(&Display::with_size(128, 64).unwrap())[63 * 128 + 127], 1.0);
+        assert_eq!(filter.apply(&Display::new()).len(), 64 * 32);
+    }
+
+    #[test]
+    fn names() {
+        for &name in Persistence::NAMES {
+            assert_eq!(Persistence::from_name(name).unwrap().name(), name);
+        }
+        assert_eq!(Persistence::OrLastTwo.next(), Persistence::Off);
+    }
+}
@@ -0,0 +1,216 @@
+use std::time::{Duration, Instant};
+
+/// Escape sequence that asks the terminal to report key presses, repeats and releases with the
+/// kitty keyboard protocol (flags: disambiguate, event types, alternate keys, all keys as escapes)
+pub const KITTY_ENABLE: &str = "\x1b[>15u";
+/// Put the keyboard back the way it was before `KITTY_ENABLE`
+pub const KITTY_DISABLE: &str = "\x1b[<u";
+/// Ask for the current kitty protocol flags, followed by a primary device attributes request
+/// which every terminal answers, so we know when to stop waiting
+pub const KITTY_QUERY: &str = "\x1b[?u\x1b[c";
+
+/// How long a key counts as held after the terminal last sent it, before it starts repeating
+pub const DEFAULT_HOLD_DELAY: Duration = Duration::from_millis(500);
+/// How long a key counts as held after the terminal last sent it, once it's repeating
+const HOLD_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How the terminal frontend finds out about key releases
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyMode {
+    /// Use the kitty protocol if the terminal supports it, otherwise `Hold`
+    Auto,
+    /// The terminal reports releases with the kitty keyboard protocol
+    Kitty,
+    /// Keys are released when the terminal stops repeating them
+    Hold
+}
+
+impl KeyMode {
+    pub const NAMES: &'static [&'static str] = &["auto", "kitty", "hold"];
+
+    pub fn from_name(name: &str) -> Option<KeyMode> {
+        match name {
+            "auto" => Some(KeyMode::Auto),
+            "kitty" => Some(KeyMode::Kitty),
+            "hold" => Some(KeyMode::Hold),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyEventKind {
+    Press,
+    Repeat,
+    Release
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub key: char,
+    pub kind: KeyEventKind
+}
+
+/// Check the terminal's answer to `KITTY_QUERY`. None if the answer isn't complete yet.
+pub fn kitty_supported(reply: &[u8]) -> Option<bool> {
+    let reply = String::from_utf8_lossy(reply);
+    // If the device attributes answer comes first the kitty query was ignored
+    reply.find("\x1b[?").and_then(|start| {
+        let rest = &reply[start + 3..];
+        rest.find(|c: char| !c.is_ascii_digit()).map(|end| rest[end..].starts_with('u'))
+    })
+}
+
+/// Parse `CSI code[:shifted[:base]] [; modifiers[:event]] u` sequences sent by a terminal using the
+/// kitty protocol. Other bytes are taken as key presses. Returns the events and the number of bytes
+/// used, anything after that is the start of a sequence that hasn't all arrived yet.
+pub fn parse_kitty(bytes: &[u8]) -> (Vec<KeyEvent>, usize) {
+    let mut events = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if !bytes[i..].starts_with(b"\x1b[") {
+            if bytes[i] != 0x1b {
+                events.push(KeyEvent { key: bytes[i] as char, kind: KeyEventKind::Press });
+            } else if i + 1 == bytes.len() {
+                break;
+            }
+            i += 1;
+            continue;
+        }
+
+        let end = match bytes[i + 2..].iter().position(|b| b.is_ascii_alphabetic() || *b == b'~') {
+            Some(end) => i + 2 + end,
+            None => break
+        };
+        let body = String::from_utf8_lossy(&bytes[i + 2..end]).into_owned();
+        if bytes[end] == b'u' {
+            if let Some(event) = parse_kitty_key(&body) {
+                events.push(event);
+            }
+        }
+        i = end + 1;
+    }
+
+    (events, i)
+}
+
+fn parse_kitty_key(body: &str) -> Option<KeyEvent> {
+    let mut fields = body.split(';');
+    let codes: Vec<u32> = fields.next()?.split(':').map(|c| c.parse().ok()).collect::<Option<_>>()?;
+    let mut modifiers = fields.next().unwrap_or("1").split(':');
+    let shift = modifiers.next().and_then(|m| m.parse::<u32>().ok()).is_some_and(|m| m.saturating_sub(1) & 1 == 1);
+    let kind = match modifiers.next() {
+        Some("2") => KeyEventKind::Repeat,
+        Some("3") => KeyEventKind::Release,
+        _ => KeyEventKind::Press
+    };
+
+    // Use the shifted key if the terminal gave us one, so shift + / is '?'
+    let code = match codes.get(1) {
+        Some(&shifted) if shift => shifted,
+        _ => codes[0]
+    };
+    ::std::char::from_u32(code).map(|key| KeyEvent { key, kind })
+}
+
+/// Works out when keys are released on terminals that only send key presses, by treating a key
+/// as held until the terminal stops repeating it
+pub struct KeyHold {
+    delay: Duration,
+    // Key, when it was last sent and whether the terminal has started repeating it
+    held: Vec<(u8, Instant, bool)>
+}
+
+impl KeyHold {
+    /// `delay` should be a little longer than the terminal's delay before keys repeat
+    pub fn new(delay: Duration) -> KeyHold {
+        KeyHold { delay, held: Vec::new() }
+    }
+
+    /// The terminal sent `key`. True if it wasn't already held.
+    pub fn press(&mut self, key: u8, now: Instant) -> bool {
+        match self.held.iter_mut().find(|&&mut (k, _, _)| k == key) {
+            Some(held) => {
+                *held = (key, now, true);
+                false
+            },
+            None => {
+                self.held.push((key, now, false));
+                true
+            }
+        }
+    }
+
+    /// Keys that haven't been sent for long enough that they must have been released
+    pub fn released(&mut self, now: Instant) -> Vec<u8> {
+        let delay = self.delay;
+        let mut released = Vec::new();
+        self.held.retain(|&(key, last_seen, repeating)| {
+            let timeout = if repeating { HOLD_INTERVAL } else { delay };
+            let held = now.duration_since(last_seen) <= timeout;
+            if !held {
+                released.push(key);
+            }
+            held
+        });
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: char) -> KeyEvent {
+        KeyEvent { key, kind: KeyEventKind::Press }
+    }
+
+    #[test]
+    fn kitty_events() {
+        let input = b"\x1b[97u\x1b[97;1:2u\x1b[97;1:3u\x1b[47:63;2u\x1b[59u";
+        let (events, used) = parse_kitty(input);
+        assert_eq!(events, vec![
+            press('a'),
+            KeyEvent { key: 'a', kind: KeyEventKind::Repeat },
+            KeyEvent { key: 'a', kind: KeyEventKind::Release },
+            press('?'),
+            press(';')
+        ]);
+        assert_eq!(used, input.len());
+    }
+
+    #[test]
+    fn kitty_partial_and_plain() {
+        assert_eq!(parse_kitty(b"x\x1b[49;1"), (vec![press('x')], 1));
+        assert_eq!(parse_kitty(b"\x1b[A1"), (vec![press('1')], 4));
+        assert_eq!(parse_kitty(b"\x1b"), (vec![], 0));
+    }
+
+    #[test]
+    fn kitty_detection() {
+        assert_eq!(kitty_supported(b"\x1b[?0u\x1b[?62;22c"), Some(true));
+        assert_eq!(kitty_supported(b"\x1b[?62;22c"), Some(false));
+        assert_eq!(kitty_supported(b"\x1b[?6"), None);
+        assert_eq!(kitty_supported(b""), None);
+        // An invalid byte comes out as a multi-byte replacement character
+        assert_eq!(kitty_supported(b"\x1b[?\xFF"), Some(false));
+    }
+
+    #[test]
+    fn held_until_repeats_stop() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut hold = KeyHold::new(DEFAULT_HOLD_DELAY);
+
+        assert!(hold.press(5, ms(0)));
+        assert_eq!(hold.released(ms(400)), vec![]);
+        assert!(!hold.press(5, ms(450)));
+        assert!(!hold.press(5, ms(480)));
+        assert_eq!(hold.released(ms(550)), vec![]);
+        assert_eq!(hold.released(ms(600)), vec![5]);
+
+        assert!(hold.press(5, ms(700)));
+        assert_eq!(hold.released(ms(1300)), vec![5]);
+    }
+}
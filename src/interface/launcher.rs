@@ -0,0 +1,266 @@
+use std::path::PathBuf;
+
+use interface::help_lines;
+use interface::text::wrap;
+use library::{Library, RecentList};
+use rom::Rom;
+
+/// Lines at the bottom of the launcher used for the selected ROM's details
+const DETAILS_LINES: usize = 5;
+
+/// A line in the list of ROMs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Row {
+    Heading(&'static str),
+    /// Index into the library's entries
+    Rom(usize)
+}
+
+/// What the user did in the launcher, mapped from whatever keys the frontend has
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LauncherInput {
+    Char(char),
+    Backspace,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Select,
+    Cancel
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LauncherState {
+    Browsing,
+    Chosen(PathBuf),
+    Cancelled
+}
+
+/// A searchable list of ROMs, most recently played first. Frontends draw `screen_lines` and
+/// pass key presses to `handle` until it returns something other than `Browsing`.
+pub struct Launcher {
+    library: Library,
+    recent: Vec<usize>,
+    query: String,
+    rows: Vec<Row>,
+    // Index into rows, always a Row::Rom unless there are no ROMs to show
+    selected: usize
+}
+
+impl Launcher {
+    pub fn new(library: Library, recent: &RecentList) -> Launcher {
+        let recent = recent.paths.iter().filter_map(|path| library.position(path)).collect();
+        let mut launcher = Launcher { library, recent, query: String::new(), rows: Vec::new(), selected: 0 };
+        launcher.update_rows();
+        launcher
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn selected_rom(&self) -> Option<&Rom> {
+        match self.rows.get(self.selected) {
+            Some(&Row::Rom(i)) => Some(&self.library.entries[i].rom),
+            _ => None
+        }
+    }
+
+    fn update_rows(&mut self) {
+        self.rows.clear();
+
+        if self.query.is_empty() && !self.recent.is_empty() {
+            self.rows.push(Row::Heading("Recently played"));
+            self.rows.extend(self.recent.iter().map(|&i| Row::Rom(i)));
+        }
+
+        let mut category = None;
+        for (i, entry) in self.library.entries.iter().enumerate() {
+            if !entry.matches(&self.query) {
+                continue;
+            }
+            if category != Some(entry.category) {
+                category = Some(entry.category);
+                self.rows.push(Row::Heading(entry.category.name()));
+            }
+            self.rows.push(Row::Rom(i));
+        }
+
+        self.selected = self.rows.iter().position(|row| matches!(row, Row::Rom(_))).unwrap_or(0);
+    }
+
+    /// Move the selection by `delta` ROMs, skipping headings
+    fn move_selection(&mut self, delta: i32) {
+        let roms: Vec<usize> = (0..self.rows.len()).filter(|&i| matches!(self.rows[i], Row::Rom(_))).collect();
+        if let Some(current) = roms.iter().position(|&i| i == self.selected) {
+            let next = (current as i32 + delta).max(0).min(roms.len() as i32 - 1);
+            self.selected = roms[next as usize];
+        }
+    }
+
+    pub fn handle(&mut self, input: LauncherInput) -> LauncherState {
+        match input {
+            LauncherInput::Char(c) if !c.is_control() => {
+                self.query.push(c);
+                self.update_rows();
+            },
+            LauncherInput::Char(_) => {},
+            LauncherInput::Backspace => {
+                self.query.pop();
+                self.update_rows();
+            },
+            LauncherInput::Up => self.move_selection(-1),
+            LauncherInput::Down => self.move_selection(1),
+            LauncherInput::PageUp => self.move_selection(-10),
+            LauncherInput::PageDown => self.move_selection(10),
+            LauncherInput::Select => {
+                if let Some(path) = self.selected_rom().and_then(|rom| rom.path.clone()) {
+                    return LauncherState::Chosen(path);
+                }
+            },
+            LauncherInput::Cancel => return LauncherState::Cancelled
+        }
+        LauncherState::Browsing
+    }
+
+    fn row_text(&self, row: Row, width: usize) -> String {
+        let text = match row {
+            Row::Heading(heading) => format!("{}:", heading),
+            Row::Rom(i) => {
+                let rom = &self.library.entries[i].rom;
+                let credits = match (&rom.author, &rom.year) {
+                    (Some(author), Some(year)) => format!("{}, {}", author, year),
+                    (Some(author), None) => author.clone(),
+                    (None, Some(year)) => year.clone(),
+                    (None, None) => String::new()
+                };
+                // Titles get at least a third of the line, the credits get cut short if they don't fit
+                let title_width = width.saturating_sub(credits.chars().count() + 3).max(width / 3);
+                let title: String = rom.title.chars().take(title_width).collect();
+                let credits: String = credits.chars().take(width.saturating_sub(title_width + 3)).collect();
+                format!("  {:<w$} {}", title, credits, w = title_width)
+            }
+        };
+        text.chars().take(width).collect()
+    }
+
+    /// The whole launcher laid out as `height` lines of at most `width` characters,
+    /// along with the index of the line to highlight
+    pub fn screen_lines(&self, width: usize, height: usize) -> (Vec<String>, Option<usize>) {
+        let mut lines = Vec::new();
+        let width = width.max(1);
+
+        if self.query.is_empty() {
+            lines.push("Type to search, arrows to choose, Enter to play, Esc to quit".chars().take(width).collect());
+        } else {
+            lines.push(format!("Search: {}_", self.query).chars().take(width).collect());
+        }
+        lines.push(String::new());
+
+        let list_height = height.saturating_sub(lines.len() + DETAILS_LINES + 1).max(1);
+        // Keep the selection in the middle of the list when there's more than fits
+        let first = self.selected.saturating_sub(list_height / 2).min(self.rows.len().saturating_sub(list_height));
+        let mut highlight = None;
+
+        if self.rows.is_empty() {
+            lines.push("  No ROMs found".to_string());
+        }
+        for (i, &row) in self.rows.iter().enumerate().skip(first).take(list_height) {
+            if i == self.selected {
+                highlight = Some(lines.len());
+            }
+            lines.push(self.row_text(row, width));
+        }
+        while lines.len() < height.saturating_sub(DETAILS_LINES) {
+            lines.push(String::new());
+        }
+
+        if let Some(rom) = self.selected_rom() {
+            let details = help_lines(rom).iter()
+                .filter(|line| !line.is_empty())
+                .flat_map(|line| wrap(line, width))
+                .take(DETAILS_LINES)
+                .collect::<Vec<_>>();
+            lines.extend(details);
+        }
+        lines.truncate(height);
+
+        (lines, highlight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn launcher(recent: &[&str]) -> Launcher {
+        let mut list = RecentList::new();
+        for path in recent.iter().rev() {
+            list.add(Path::new(path));
+        }
+        Launcher::new(Library::scan("examples").unwrap(), &list)
+    }
+
+    const PONG: &str = "examples/games/Pong [Paul Vervalin, 1990].ch8";
+
+    #[test]
+    fn recent_roms_come_first() {
+        let launcher = launcher(&[PONG, "examples/games/gone.ch8"]);
+        assert_eq!(launcher.rows()[0], Row::Heading("Recently played"));
+        assert_eq!(launcher.rows()[2], Row::Heading("Games"));
+        assert_eq!(launcher.selected_rom().unwrap().title, "Pong");
+    }
+
+    #[test]
+    fn search_and_select() {
+        let mut launcher = launcher(&[PONG]);
+        for c in "winter".chars() {
+            assert_eq!(launcher.handle(LauncherInput::Char(c)), LauncherState::Browsing);
+        }
+        assert!(launcher.rows().iter().all(|&row| match row {
+            Row::Rom(i) => launcher.library.entries[i].rom.author.as_ref().unwrap().contains("Winter"),
+            Row::Heading(heading) => heading != "Recently played"
+        }));
+
+        let first = launcher.selected_rom().unwrap().path.clone().unwrap();
+        launcher.handle(LauncherInput::Up);
+        assert_eq!(launcher.handle(LauncherInput::Select), LauncherState::Chosen(first.clone()));
+        launcher.handle(LauncherInput::Down);
+        assert_ne!(launcher.handle(LauncherInput::Select), LauncherState::Chosen(first));
+
+        for _ in 0.."winter".len() {
+            launcher.handle(LauncherInput::Backspace);
+        }
+        assert_eq!(launcher.rows()[0], Row::Heading("Recently played"));
+        assert_eq!(launcher.handle(LauncherInput::Cancel), LauncherState::Cancelled);
+    }
+
+    #[test]
+    fn nothing_found() {
+        let mut launcher = launcher(&[]);
+        for c in "no such rom".chars() {
+            launcher.handle(LauncherInput::Char(c));
+        }
+        assert!(launcher.rows().is_empty());
+        assert_eq!(launcher.handle(LauncherInput::Select), LauncherState::Browsing);
+        let (lines, highlight) = launcher.screen_lines(40, 12);
+        assert_eq!(lines[2], "  No ROMs found");
+        assert_eq!(highlight, None);
+    }
+
+    #[test]
+    fn screen_layout() {
+        let launcher = launcher(&[PONG]);
+        let (lines, highlight) = launcher.screen_lines(40, 16);
+        assert!(lines.len() <= 16 && lines.iter().all(|line| line.chars().count() <= 40));
+        assert_eq!(highlight, Some(3));
+        assert!(lines[3].starts_with("  Pong"));
+        assert!(lines[3].ends_with("Paul Vervalin, 1990"));
+        assert_eq!(lines[11], "Pong");
+    }
+}
@@ -0,0 +1,546 @@
+use cheats::{Comparison, RamSearch};
+use cpu::Cpu;
+use interface::Input;
+
+/// Bytes shown on each row of the hex view
+pub const BYTES_PER_ROW: usize = 16;
+/// Rows of memory shown at once
+pub const MEMORY_ROWS: usize = 16;
+/// Lines returned by `MemoryView::lines`
+pub const LINES: usize = 3 + 1 + MEMORY_ROWS + 1 + 1;
+/// Frames a byte stays highlighted after something writes to it
+const WRITE_FRAMES: u8 = 30;
+/// Addresses listed while searching, once there are few enough left
+const LISTED_CANDIDATES: usize = 8;
+
+/// A register that can be shown and edited alongside memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    V(u8),
+    I,
+    PC,
+    DT,
+    ST
+}
+
+impl Register {
+    /// Every register, in the order they're shown
+    pub const ALL: [Register; 20] = [
+        Register::V(0x0), Register::V(0x1), Register::V(0x2), Register::V(0x3),
+        Register::V(0x4), Register::V(0x5), Register::V(0x6), Register::V(0x7),
+        Register::V(0x8), Register::V(0x9), Register::V(0xA), Register::V(0xB),
+        Register::V(0xC), Register::V(0xD), Register::V(0xE), Register::V(0xF),
+        Register::I, Register::PC, Register::DT, Register::ST
+    ];
+
+    pub fn name(&self) -> String {
+        match *self {
+            Register::V(x) => format!("V{:X}", x),
+            Register::I => "I".to_string(),
+            Register::PC => "PC".to_string(),
+            Register::DT => "DT".to_string(),
+            Register::ST => "ST".to_string()
+        }
+    }
+
+    /// Number of hex digits in the value
+    pub fn digits(&self) -> usize {
+        match *self {
+            Register::I | Register::PC => 3,
+            _ => 2
+        }
+    }
+
+    pub fn get(&self, cpu: &Cpu) -> u16 {
+        match *self {
+            Register::V(x) => cpu.v_reg[x as usize] as u16,
+            Register::I => cpu.i_reg,
+            Register::PC => cpu.prog_counter,
+            Register::DT => cpu.delay_timer as u16,
+            Register::ST => cpu.sound_timer as u16
+        }
+    }
+
+    fn set(&self, cpu: &mut Cpu, value: u16) {
+        match *self {
+            Register::V(x) => cpu.v_reg[x as usize] = value as u8,
+            Register::I => cpu.i_reg = value & 0xFFF,
+            // Leave room for a whole instruction
+            Register::PC => cpu.prog_counter = value.min(cpu.memory.len() as u16 - 2),
+            Register::DT => cpu.delay_timer = value as u8,
+            Register::ST => cpu.sound_timer = value as u8
+        }
+    }
+}
+
+/// A change the user made to the machine in the memory view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edit {
+    Byte(u16, u8),
+    Register(Register, u16)
+}
+
+impl Edit {
+    pub fn apply(&self, cpu: &mut Cpu) {
+        match *self {
+            Edit::Byte(addr, value) => { cpu.write_bytes(addr, &[value]); },
+            Edit::Register(register, value) => register.set(cpu, value)
+        }
+    }
+}
+
+/// What the user did in the memory view, mapped from whatever keys the frontend has
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemoryInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    /// Switch between memory and the registers
+    SwitchFocus,
+    /// Move the cursor to the address in I
+    GoToI,
+    /// Move the cursor to the program counter
+    GoToPc,
+    /// Stop or start the machine
+    Pause,
+    /// A hex digit, only used while paused
+    Digit(u8),
+    Enter,
+    /// Forget the digits typed so far
+    Cancel,
+    /// Start a RAM search with every address as a candidate
+    NewSearch,
+    /// Narrow the RAM search down to the addresses that changed like this since the last time
+    Search(Comparison)
+}
+
+/// The keys for RAM searches, which are the same everywhere
+pub fn search_input(key: char) -> Option<MemoryInput> {
+    match key {
+        'n' => Some(MemoryInput::NewSearch),
+        '=' => Some(MemoryInput::Search(Comparison::Unchanged)),
+        '!' => Some(MemoryInput::Search(Comparison::Changed)),
+        '-' => Some(MemoryInput::Search(Comparison::Decreased)),
+        '+' => Some(MemoryInput::Search(Comparison::Increased)),
+        _ => None
+    }
+}
+
+/// Why a piece of text is highlighted, for frontends to pick colours
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    None,
+    /// Written to by Fx33 or Fx55 in the last half a second
+    Written,
+    /// The sprite drawn most recently
+    Sprite,
+    /// The address in I
+    I,
+    /// The instruction at the program counter
+    Pc,
+    /// The byte or register under the cursor
+    Cursor,
+    /// The digits being typed
+    Entry,
+    /// Still a candidate in the RAM search
+    Candidate,
+    /// Headings and hints
+    Label
+}
+
+/// Text with one highlight, lines are made of several of these
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub highlight: Highlight
+}
+
+fn span<S: Into<String>>(text: S, highlight: Highlight) -> Span {
+    Span { text: text.into(), highlight }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Focus {
+    Memory,
+    /// Index into `Register::ALL`
+    Register(usize)
+}
+
+/// A live hex view of memory and the registers. Frontends call `update` every frame and draw `lines`,
+/// and pass key presses to `handle` and send whatever it returns to the emulator.
+/// Bytes and registers can only be edited while paused.
+pub struct MemoryView {
+    /// Address of the byte under the cursor
+    cursor: u16,
+    /// Address of the first row shown
+    top: u16,
+    focus: Focus,
+    paused: bool,
+    // Hex digits typed so far
+    entry: String,
+    // Memory as it was last frame, to spot writes
+    previous: Vec<u8>,
+    // Frames left to highlight each byte
+    written: Vec<u8>,
+    search: Option<RamSearch>,
+    // I and the program counter as they were last frame, for `handle`
+    i_reg: u16,
+    prog_counter: u16
+}
+
+impl MemoryView {
+    pub fn new() -> MemoryView {
+        MemoryView {
+            cursor: 0x200, top: 0x200, focus: Focus::Memory, paused: false, entry: String::new(),
+            previous: Vec::new(), written: Vec::new(), search: None, i_reg: 0, prog_counter: 0
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Look for bytes that changed since the last frame
+    pub fn update(&mut self, cpu: &Cpu) {
+        self.i_reg = cpu.i_reg;
+        self.prog_counter = cpu.prog_counter;
+        if self.previous.len() != cpu.memory.len() {
+            self.previous = cpu.memory.clone();
+            self.written = vec![0; cpu.memory.len()];
+            return;
+        }
+        for ((previous, &byte), written) in self.previous.iter_mut().zip(&cpu.memory).zip(&mut self.written) {
+            if *previous != byte {
+                *previous = byte;
+                *written = WRITE_FRAMES;
+            } else {
+                *written = written.saturating_sub(1);
+            }
+        }
+    }
+
+    fn move_cursor(&mut self, by: i32) {
+        let last = self.previous.len().max(1) as i32 - 1;
+        match self.focus {
+            Focus::Memory => self.go_to((self.cursor as i32 + by).max(0).min(last) as u16),
+            Focus::Register(i) => {
+                let i = (i as i32 + by).max(0).min(Register::ALL.len() as i32 - 1);
+                self.focus = Focus::Register(i as usize);
+            }
+        }
+        self.entry.clear();
+    }
+
+    /// Put the cursor on `addr`, scrolling so it can be seen
+    fn go_to(&mut self, addr: u16) {
+        self.cursor = addr;
+        let row = addr - addr % BYTES_PER_ROW as u16;
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + (MEMORY_ROWS * BYTES_PER_ROW) as u16 {
+            self.top = row - ((MEMORY_ROWS - 1) * BYTES_PER_ROW) as u16;
+        }
+    }
+
+    /// The value typed so far as an edit, if it's a whole one
+    fn entered(&self) -> Option<Edit> {
+        let value = u16::from_str_radix(&self.entry, 16).ok()?;
+        match self.focus {
+            Focus::Memory => Some(Edit::Byte(self.cursor, value as u8)),
+            Focus::Register(i) => Some(Edit::Register(Register::ALL[i], value))
+        }
+    }
+
+    /// Finish typing a value, moving on to the next byte or register
+    fn commit(&mut self) -> Option<Input> {
+        let edit = self.entered();
+        self.entry.clear();
+        if edit.is_some() {
+            self.move_cursor(1);
+        }
+        edit.map(Input::Edit)
+    }
+
+    /// Handle a key press, returning what the emulator should do about it
+    pub fn handle(&mut self, input: MemoryInput) -> Option<Input> {
+        let (row, page) = (BYTES_PER_ROW as i32, (MEMORY_ROWS * BYTES_PER_ROW) as i32);
+        let last_page = self.previous.len().saturating_sub(page as usize) as u16;
+        match input {
+            MemoryInput::Up => self.move_cursor(if self.focus == Focus::Memory { -row } else { -8 }),
+            MemoryInput::Down => self.move_cursor(if self.focus == Focus::Memory { row } else { 8 }),
+            MemoryInput::Left => self.move_cursor(-1),
+            MemoryInput::Right => self.move_cursor(1),
+            MemoryInput::PageUp => if self.focus == Focus::Memory {
+                self.top = self.top.saturating_sub(page as u16);
+                self.move_cursor(-page);
+            },
+            MemoryInput::PageDown => if self.focus == Focus::Memory {
+                self.top = (self.top + page as u16).min(last_page);
+                self.move_cursor(page);
+            },
+            MemoryInput::SwitchFocus => {
+                self.focus = match self.focus {
+                    Focus::Memory => Focus::Register(0),
+                    Focus::Register(_) => Focus::Memory
+                };
+                self.entry.clear();
+            },
+            MemoryInput::GoToI => {
+                self.focus = Focus::Memory;
+                self.cursor = self.i_reg;
+                self.move_cursor(0);
+            },
+            MemoryInput::GoToPc => {
+                self.focus = Focus::Memory;
+                self.cursor = self.prog_counter;
+                self.move_cursor(0);
+            },
+            MemoryInput::Pause => {
+                self.paused = !self.paused;
+                self.entry.clear();
+            },
+            MemoryInput::Digit(digit) if self.paused && digit < 16 => {
+                self.entry += &format!("{:X}", digit);
+                let digits = match self.focus {
+                    Focus::Memory => 2,
+                    Focus::Register(i) => Register::ALL[i].digits()
+                };
+                if self.entry.len() == digits {
+                    return self.commit();
+                }
+            },
+            MemoryInput::Digit(_) => {},
+            MemoryInput::Enter => return self.commit(),
+            MemoryInput::Cancel => self.entry.clear(),
+            MemoryInput::NewSearch => self.search = Some(RamSearch::new(&self.previous)),
+            MemoryInput::Search(comparison) => if let Some(ref mut search) = self.search {
+                search.narrow(&self.previous, comparison);
+            }
+        }
+        None
+    }
+
+    /// The highlight for the byte at `addr`, the most important one if there are several
+    fn highlight(&self, cpu: &Cpu, addr: u16) -> Highlight {
+        let within = |start: u16, len: u16| addr >= start && addr < start + len;
+        if self.focus == Focus::Memory && addr == self.cursor {
+            if self.entry.is_empty() { Highlight::Cursor } else { Highlight::Entry }
+        } else if within(cpu.prog_counter, 2) {
+            Highlight::Pc
+        } else if addr == cpu.i_reg {
+            Highlight::I
+        } else if cpu.last_sprite.is_some_and(|(start, rows)| within(start, rows as u16)) {
+            Highlight::Sprite
+        } else if self.written.get(addr as usize).is_some_and(|&frames| frames > 0) {
+            Highlight::Written
+        } else if self.search.as_ref().is_some_and(|search| search.candidates().binary_search(&addr).is_ok()) {
+            Highlight::Candidate
+        } else {
+            Highlight::None
+        }
+    }
+
+    /// What to draw: three lines of registers, a blank line, the rows of memory, the RAM search and a hint.
+    /// Every line is `BYTES_PER_ROW * 3 + 5` characters or less.
+    pub fn lines(&self, cpu: &Cpu) -> Vec<Vec<Span>> {
+        let mut lines = Vec::new();
+
+        for registers in Register::ALL.chunks(8) {
+            let mut line = Vec::new();
+            for &register in registers {
+                let i = Register::ALL.iter().position(|&r| r == register).unwrap();
+                let selected = self.focus == Focus::Register(i);
+                let value = if selected && !self.entry.is_empty() {
+                    span(format!("{:<w$}", self.entry, w = register.digits()), Highlight::Entry)
+                } else {
+                    let highlight = if selected { Highlight::Cursor } else { Highlight::None };
+                    span(format!("{:0w$X}", register.get(cpu), w = register.digits()), highlight)
+                };
+                line.push(span(format!("{}=", register.name()), Highlight::Label));
+                line.push(value);
+                line.push(span(" ", Highlight::None));
+            }
+            lines.push(line);
+        }
+        let status = if self.paused { "paused" } else { "running" };
+        lines[2].push(span(format!(" SP={:X} {}", cpu.stack_pointer, status), Highlight::Label));
+        lines.push(Vec::new());
+
+        for row in 0..MEMORY_ROWS {
+            let start = self.top as usize + row * BYTES_PER_ROW;
+            if start >= cpu.memory.len() {
+                lines.push(Vec::new());
+                continue;
+            }
+            let mut line = vec![span(format!("{:03X}: ", start), Highlight::Label)];
+            for addr in start..(start + BYTES_PER_ROW).min(cpu.memory.len()) {
+                let highlight = self.highlight(cpu, addr as u16);
+                let text = if highlight == Highlight::Entry {
+                    format!("{:<2}", self.entry)
+                } else {
+                    format!("{:02X}", cpu.memory[addr])
+                };
+                line.push(span(text, highlight));
+                line.push(span(" ", Highlight::None));
+            }
+            lines.push(line);
+        }
+
+        lines.push(match self.search {
+            Some(ref search) => {
+                let candidates = search.candidates();
+                let mut text = format!(" {} left", candidates.len());
+                if candidates.len() <= LISTED_CANDIDATES {
+                    text += ":";
+                    for addr in candidates {
+                        text += &format!(" {:03X}", addr);
+                    }
+                }
+                vec![span("search:", Highlight::Label), span(text, Highlight::Candidate)]
+            },
+            None => vec![span("n searches RAM, = ! - + narrow it down", Highlight::Label)]
+        });
+
+        let hint = if self.paused {
+            "hjkl/tab move, 0-F edit, i/p go to I/PC, space runs"
+        } else {
+            "hjkl/tab move, i/p go to I/PC, space pauses to edit"
+        };
+        lines.push(vec![span(hint, Highlight::Label)]);
+        lines
+    }
+}
+
+impl Default for MemoryView {
+    fn default() -> MemoryView {
+        MemoryView::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rom::Rom;
+
+    fn text(line: &[Span]) -> String {
+        line.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    fn highlighted(lines: &[Vec<Span>], highlight: Highlight) -> Vec<String> {
+        lines.iter().flat_map(|line| line.iter()).filter(|s| s.highlight == highlight).map(|s| s.text.clone()).collect()
+    }
+
+    #[test]
+    fn highlights() {
+        // LD I, 0x20C / LD V0, 0xAB / LD B, V0 / DRW V0, V0, 2 / JP 0x208 / sprite
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&Rom::from_bytes(vec![0xA2, 0x0C, 0x60, 0xAB, 0xF0, 0x33, 0xD0, 0x02, 0x12, 0x08,
+                                           0x00, 0x00, 0x81, 0x18]).unwrap()).unwrap();
+        let mut view = MemoryView::new();
+        view.update(&cpu);
+        for _ in 0..3 {
+            cpu.tick();
+        }
+        view.update(&cpu);
+        cpu.tick();
+
+        let lines = view.lines(&cpu);
+        assert_eq!(lines.len(), LINES);
+        assert_eq!(text(&lines[0]), "V0=AB V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00 ");
+        assert!(text(&lines[2]).starts_with("I=20C PC=208 DT=00 ST=00  SP=0 running"));
+        assert!(text(&lines[4]).starts_with("200: A2 0C 60 AB"));
+        assert_eq!(highlighted(&lines, Highlight::Cursor), vec!["A2"]);
+        assert_eq!(highlighted(&lines, Highlight::Pc), vec!["12", "08"]);
+        // 0xAB is 171, written at I. The sprite is the first two bytes of that.
+        assert_eq!(highlighted(&lines, Highlight::I), vec!["01"]);
+        assert_eq!(highlighted(&lines, Highlight::Sprite), vec!["07"]);
+        assert_eq!(highlighted(&lines, Highlight::Written), vec!["01"]);
+        assert!(lines.iter().all(|line| text(line).len() <= BYTES_PER_ROW * 3 + 5));
+    }
+
+    #[test]
+    fn editing() {
+        let mut cpu = Cpu::new();
+        let mut view = MemoryView::new();
+        view.update(&cpu);
+        view.handle(MemoryInput::Down);
+        view.handle(MemoryInput::Right);
+        // Only while paused
+        assert_eq!(view.handle(MemoryInput::Digit(0xA)), None);
+        view.handle(MemoryInput::Pause);
+        assert!(view.paused());
+        assert_eq!(view.handle(MemoryInput::Digit(0xA)), None);
+        assert_eq!(view.lines(&cpu)[5][3], span("A ", Highlight::Entry));
+        assert_eq!(view.handle(MemoryInput::Digit(0x5)), Some(Input::Edit(Edit::Byte(0x211, 0xA5))));
+        // Moves on to the next byte
+        assert_eq!(view.handle(MemoryInput::Digit(0x1)), None);
+        assert_eq!(view.handle(MemoryInput::Enter), Some(Input::Edit(Edit::Byte(0x212, 0x01))));
+
+        view.handle(MemoryInput::SwitchFocus);
+        view.handle(MemoryInput::Down);
+        view.handle(MemoryInput::Down);
+        view.handle(MemoryInput::Right);
+        for digit in [0x3, 0x0] {
+            view.handle(MemoryInput::Digit(digit));
+        }
+        view.handle(MemoryInput::Cancel);
+        for digit in [0x3, 0x0] {
+            view.handle(MemoryInput::Digit(digit));
+        }
+        let edit = view.handle(MemoryInput::Digit(0x4));
+        assert_eq!(edit, Some(Input::Edit(Edit::Register(Register::PC, 0x304))));
+
+        Edit::Register(Register::PC, 0x304).apply(&mut cpu);
+        Edit::Register(Register::V(3), 0x1FF).apply(&mut cpu);
+        Edit::Byte(0x211, 0xA5).apply(&mut cpu);
+        assert_eq!((cpu.prog_counter, cpu.v_reg[3], cpu.memory[0x211]), (0x304, 0xFF, 0xA5));
+    }
+
+    #[test]
+    fn scrolling() {
+        let mut cpu = Cpu::new();
+        cpu.i_reg = 0xF00;
+        let mut view = MemoryView::new();
+        view.update(&cpu);
+        view.handle(MemoryInput::GoToI);
+        assert!(text(&view.lines(&cpu)[4]).starts_with("E10: "));
+        for _ in 0..20 {
+            view.handle(MemoryInput::PageDown);
+        }
+        assert!(text(&view.lines(&cpu)[4]).starts_with("F00: "));
+        assert_eq!(view.cursor, 0xFFF);
+        view.handle(MemoryInput::GoToPc);
+        assert!(text(&view.lines(&cpu)[4]).starts_with("000: "));
+    }
+
+    #[test]
+    fn ram_search() {
+        let mut cpu = Cpu::new();
+        let mut view = MemoryView::new();
+        view.update(&cpu);
+        assert_eq!(text(&view.lines(&cpu)[20]), "n searches RAM, = ! - + narrow it down");
+        view.handle(MemoryInput::NewSearch);
+        assert_eq!(text(&view.lines(&cpu)[20]), "search: 4096 left");
+
+        cpu.memory[0x300] = 3;
+        cpu.memory[0x302] = 3;
+        view.update(&cpu);
+        view.handle(search_input('!').unwrap());
+        cpu.memory[0x302] = 2;
+        view.update(&cpu);
+        view.handle(search_input('=').unwrap());
+        assert_eq!(text(&view.lines(&cpu)[20]), "search: 1 left: 300");
+
+        cpu.prog_counter = 0x3F0;
+        view.update(&cpu);
+        view.handle(MemoryInput::GoToPc);
+        // Once it's no longer highlighted as just written
+        for _ in 0..30 {
+            view.update(&cpu);
+        }
+        assert_eq!(highlighted(&view.lines(&cpu), Highlight::Candidate), vec!["03", " 1 left: 300"]);
+    }
+}
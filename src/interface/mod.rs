@@ -1,32 +1,281 @@
-
+#[cfg(feature = "termion")]
 pub mod termion;
+#[cfg(feature = "sdl")]
 pub mod sdl;
+pub mod cli;
+pub mod text;
+pub mod launcher;
+pub mod filter;
+pub mod cells;
+pub mod memory;
+pub mod sprites;
+pub mod keys;
+#[cfg(feature = "sdl")]
+pub mod palette;
+
+pub use self::cli::parse_args_and_run;
+
+use std::fs;
+use std::path::PathBuf;
+use std::slice;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use cpu::{Cpu, FRAME_HZ};
+use interface::memory::Edit;
+use rom::Rom;
+
+/// Something the user did that the emulator needs to know about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    KeyDown(u8),
+    KeyUp(u8),
+    /// Start the program again, keeping what's in memory
+    Reset,
+    /// Load the ROM from disk again
+    Reload,
+    /// Change memory or a register
+    Edit(Edit)
+}
+
+/// Why `run` returned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    /// The user asked to quit
+    Quit,
+    /// The ROM should be loaded from disk again and run in the same frontend
+    Reload
+}
+
+/// Notices when a file changes, by checking when it was last modified
+pub struct FileWatch {
+    path: PathBuf,
+    modified: Option<SystemTime>
+}
+
+impl FileWatch {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileWatch {
+        let path = path.into();
+        let modified = FileWatch::modified(&path);
+        FileWatch { path, modified }
+    }
+
+    fn modified(path: &PathBuf) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// True if the file has been modified since the last call. A file that's gone missing
+    /// doesn't count until it's back.
+    pub fn changed(&mut self) -> bool {
+        match FileWatch::modified(&self.path) {
+            Some(modified) if Some(modified) != self.modified => {
+                self.modified = Some(modified);
+                true
+            },
+            _ => false
+        }
+    }
+}
+
+/// A way of presenting the emulator to the user, e.g. a terminal or a window.
+///
+/// The frontend only deals with input and output, the machine itself is driven by `run`.
+pub trait Frontend {
+    /// Draw the current state of the machine
+    fn render(&mut self, cpu: &Cpu);
+
+    /// Draw several machines at once. Frontends that can only show one draw the first.
+    fn render_many(&mut self, cpus: &[Cpu]) {
+        self.render(&cpus[0]);
+    }
+
+    /// Input received since the last call
+    fn poll_input(&mut self) -> Vec<Input>;
+
+    /// Turn the beeper on or off, called every frame
+    fn play_audio(&mut self, beeping: bool);
+
+    /// True once the user has asked to quit
+    fn should_quit(&self) -> bool;
+
+    /// True while the user has stopped the machine, which is still drawn but doesn't run
+    fn paused(&self) -> bool {
+        false
+    }
+}
+
+/// Which keyboard key is used for each key on the CHIP-8 hex keypad
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    // keys[n] is the keyboard key for keypad key n
+    keys: [char; 16]
+}
+
+impl Keymap {
+    /// Names accepted by `from_name`, as well as a string of 16 keys for keypad keys 0 to F
+    pub const NAMES: &'static [&'static str] = &["qwerty", "azerty"];
+
+    /// Use the left hand side of a QWERTY keyboard
+    ///
+    /// ```text
+    /// 1 2 3 4      1 2 3 C
+    /// q w e r  ->  4 5 6 D
+    /// a s d f      7 8 9 E
+    /// z x c v      A 0 B F
+    /// ```
+    pub fn qwerty() -> Keymap {
+        Keymap::from_keys("x123qweasdzc4rfv").unwrap()
+    }
+
+    /// The same physical keys as `qwerty` on an AZERTY keyboard
+    pub fn azerty() -> Keymap {
+        Keymap::from_keys("x123azeqsdwc4rfv").unwrap()
+    }
 
-pub fn parse_args_and_run(args: Vec<String>) {
-    let mut debug = false;
-    let mut filepath = None;
-    let mut termion_mode = true;
+    /// `keys` is the keyboard key for each keypad key from 0 to F, e.g. "x123qweasdzc4rfv"
+    pub fn from_keys(keys: &str) -> Option<Keymap> {
+        let chars: Vec<char> = keys.chars().map(|c| c.to_ascii_lowercase()).collect();
+        if chars.len() != 16 {
+            return None;
+        }
+        let mut map = Keymap { keys: ['\0'; 16] };
+        map.keys.copy_from_slice(&chars);
+        Some(map)
+    }
 
-    for (i, arg) in args.iter().enumerate() {
-        if arg == "-d" {
-            debug = true;
+    pub fn from_name(name: &str) -> Option<Keymap> {
+        match name {
+            "qwerty" => Some(Keymap::qwerty()),
+            "azerty" => Some(Keymap::azerty()),
+            keys => Keymap::from_keys(keys)
         }
+    }
+
+    /// The keypad key for a key on the keyboard
+    pub fn keypad_key(&self, key: char) -> Option<u8> {
+        let key = key.to_ascii_lowercase();
+        self.keys.iter().position(|&k| k == key).map(|i| i as u8)
+    }
+}
+
+/// What to show when the user asks for help with a ROM, one paragraph per line
+pub fn help_lines(rom: &Rom) -> Vec<String> {
+    let mut lines = vec![rom.title.clone()];
+
+    match (&rom.author, &rom.year) {
+        (Some(author), Some(year)) => lines.push(format!("By {}, {}", author, year)),
+        (Some(author), None) => lines.push(format!("By {}", author)),
+        (None, Some(year)) => lines.push(year.clone()),
+        (None, None) => {}
+    }
+    lines.push(format!("System: {}", rom.system().name()));
+    lines.push(String::new());
+
+    match rom.docs {
+        Some(ref docs) if !docs.controls.is_empty() => {
+            lines.push("Controls:".to_string());
+            lines.extend(docs.controls.iter().map(|c| format!("- {}", c)));
+        },
+        Some(_) => lines.push("The documentation doesn't mention any controls".to_string()),
+        None => lines.push("No documentation found".to_string())
+    }
+
+    lines
+}
+
+/// Run `cpu` at `speed` instructions per second until the frontend reports that the user wants to quit,
+/// or asks for the ROM to be reloaded
+pub fn run<F: Frontend>(frontend: &mut F, cpu: &mut Cpu, speed: u32) -> Stop {
+    run_many(frontend, slice::from_mut(cpu), speed, None)
+}
+
+/// Run several machines side by side, all given the same input. It beeps while any of them beeps.
+/// If `watch` is given, returns `Stop::Reload` when the file changes.
+pub fn run_many<F: Frontend>(frontend: &mut F, cpus: &mut [Cpu], speed: u32, mut watch: Option<&mut FileWatch>)
+                             -> Stop {
+    let frame_time = Duration::from_secs(1) / FRAME_HZ;
+
+    while !frontend.should_quit() {
+        let frame_start = Instant::now();
 
-        if arg == "-f" {
-            match args.get(i + 1) {
-                Some(arg) => filepath = Some(arg.as_str()),
-                None => {}
+        let inputs = frontend.poll_input();
+        if inputs.contains(&Input::Reload) || watch.as_mut().is_some_and(|watch| watch.changed()) {
+            return Stop::Reload;
+        }
+        for cpu in cpus.iter_mut() {
+            for input in &inputs {
+                match *input {
+                    Input::KeyDown(key) => { cpu.press_key(key); },
+                    Input::KeyUp(key) => { cpu.release_key(key); },
+                    Input::Reset => cpu.reset(),
+                    Input::Edit(edit) => edit.apply(cpu),
+                    Input::Reload => {}
+                }
+            }
+            if !frontend.paused() {
+                cpu.run_frame(speed);
             }
         }
 
-        if arg == "--sdl" {
-            termion_mode = false;
+        frontend.play_audio(cpus.iter().any(|cpu| cpu.sound_timer > 0));
+        frontend.render_many(cpus);
+
+        let elapsed = frame_start.elapsed();
+        if elapsed < frame_time {
+            thread::sleep(frame_time - elapsed);
+        }
+    }
+    Stop::Quit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qwerty_layout() {
+        let keymap = Keymap::qwerty();
+        let rows = ["1234", "qwer", "asdf", "zxcv"];
+        let expected = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+        for (row, keys) in rows.iter().zip(expected.iter()) {
+            for (c, &key) in row.chars().zip(keys.iter()) {
+                assert_eq!(keymap.keypad_key(c), Some(key));
+            }
         }
+        assert_eq!(keymap.keypad_key('Q'), Some(0x4));
+        assert_eq!(keymap.keypad_key('p'), None);
+    }
+
+    #[test]
+    fn keymap_from_name() {
+        assert_eq!(Keymap::from_name("azerty").unwrap().keypad_key('a'), Some(0x4));
+        assert_eq!(Keymap::from_name("0123456789abcdef").unwrap().keypad_key('b'), Some(0xB));
+        assert_eq!(Keymap::from_name("dvorak"), None);
+    }
+
+    #[test]
+    fn file_watch() {
+        let path = ::std::env::temp_dir().join(format!("rust-chip8-watch-test-{}", ::std::process::id()));
+        fs::write(&path, [0x12, 0x00]).unwrap();
+        let mut watch = FileWatch::new(&path);
+        assert!(!watch.changed());
+
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        assert!(watch.changed());
+        assert!(!watch.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watch.changed());
     }
 
-    if termion_mode {
-        termion::run(filepath, debug);
-    } else {
-        sdl::run(filepath, debug);
+    #[test]
+    fn help_for_documented_rom() {
+        let rom = Rom::from_path("examples/games/Pong [Paul Vervalin, 1990].ch8").unwrap();
+        assert_eq!(help_lines(&rom), vec![
+            "Pong", "By Paul Vervalin, 1990", "System: CHIP-8", "", "Controls:",
+            "- Use keys 7 and 4 move left player and / and * move right player"
+        ]);
+        assert_eq!(help_lines(&Rom::from_bytes(vec![0x12, 0x00]).unwrap()).last().unwrap(), "No documentation found");
     }
-}
\ No newline at end of file
+}
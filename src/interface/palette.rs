@@ -0,0 +1,120 @@
+use sdl2::pixels::Color;
+
+/// Colours used to draw the display.
+///
+/// CHIP-8 and SUPER-CHIP only use `foreground` and `background`. XO-CHIP has two bit planes,
+/// where `foreground` is used for pixels only set in the first plane, `plane_2` for pixels only
+/// set in the second and `both_planes` for pixels set in both.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub foreground: Color,
+    pub background: Color,
+    pub plane_2: Color,
+    pub both_planes: Color
+}
+
+impl Palette {
+    /// Names accepted by `from_name`, as well as hex colours
+    pub const NAMES: &'static [&'static str] = &["classic", "green", "amber", "lcd"];
+
+    /// White on black
+    pub fn new() -> Palette {
+        Palette::from_colours(Color::RGB(255, 255, 255), Color::RGB(0, 0, 0))
+    }
+
+    /// A palette with plane colours picked to go with `foreground` and `background`
+    pub fn from_colours(foreground: Color, background: Color) -> Palette {
+        let half_way = |f: u8, b: u8| ((f as u16 + b as u16) / 2) as u8;
+        let plane_2 = Color::RGB(half_way(foreground.r, background.r), half_way(foreground.g, background.g),
+                                 half_way(foreground.b, background.b));
+        Palette { foreground, background, plane_2, both_planes: foreground }
+    }
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        match name {
+            "classic" => Some(Palette::new()),
+            // Green phosphor monitor
+            "green" => Some(Palette::from_colours(Color::RGB(0x33, 0xFF, 0x66), Color::RGB(0x05, 0x1A, 0x0A))),
+            // Amber phosphor monitor
+            "amber" => Some(Palette::from_colours(Color::RGB(0xFF, 0xB0, 0x00), Color::RGB(0x1A, 0x0F, 0x00))),
+            // Greenish LCD like an old handheld
+            "lcd" => Some(Palette {
+                foreground: Color::RGB(0x0F, 0x38, 0x0F),
+                background: Color::RGB(0x9B, 0xBC, 0x0F),
+                plane_2: Color::RGB(0x8B, 0xAC, 0x0F),
+                both_planes: Color::RGB(0x30, 0x62, 0x30)
+            }),
+            _ => None
+        }
+    }
+
+    /// Parse either a name from `NAMES` or hex colours "FG,BG" or "FG,BG,PLANE2,BOTH"
+    pub fn parse(s: &str) -> Option<Palette> {
+        if let Some(palette) = Palette::from_name(s) {
+            return Some(palette);
+        }
+
+        let colours: Vec<Color> = s.split(',').map(parse_hex_colour).collect::<Option<_>>()?;
+        match *colours.as_slice() {
+            [foreground, background] => Some(Palette::from_colours(foreground, background)),
+            [foreground, background, plane_2, both_planes] => Some(Palette { foreground, background, plane_2, both_planes }),
+            _ => None
+        }
+    }
+
+    /// Colour of a pixel given which planes are set in it, bit 0 for the first plane and bit 1 for the second
+    pub fn colour(&self, planes: u8) -> Color {
+        match planes & 0b11 {
+            0b00 => self.background,
+            0b01 => self.foreground,
+            0b10 => self.plane_2,
+            _ => self.both_planes
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new()
+    }
+}
+
+fn parse_hex_colour(s: &str) -> Option<Color> {
+    let s = s.trim().trim_start_matches('#');
+    if s.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(s, 16).ok()?;
+    Some(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_palettes() {
+        for &name in Palette::NAMES {
+            assert!(Palette::parse(name).is_some(), "{}", name);
+        }
+        assert_eq!(Palette::parse("classic"), Some(Palette::new()));
+        assert_eq!(Palette::parse("sepia"), None);
+    }
+
+    #[test]
+    fn hex_palettes() {
+        let palette = Palette::parse("#ff0000,000000").unwrap();
+        assert_eq!(palette.colour(0b01), Color::RGB(255, 0, 0));
+        assert_eq!(palette.colour(0b00), Color::RGB(0, 0, 0));
+        assert_eq!(palette.colour(0b10), Color::RGB(127, 0, 0));
+        assert_eq!(palette.colour(0b11), Color::RGB(255, 0, 0));
+
+        let palette = Palette::parse("ffffff,000000,ff0000,00ff00").unwrap();
+        assert_eq!(palette.colour(0b10), Color::RGB(255, 0, 0));
+        assert_eq!(palette.colour(0b11), Color::RGB(0, 255, 0));
+
+        assert_eq!(Palette::parse("ffffff"), None);
+        assert_eq!(Palette::parse("ffffff,000000,ff0000"), None);
+        assert_eq!(Palette::parse("ffffff,00000g"), None);
+    }
+}
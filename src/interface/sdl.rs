@@ -1,76 +1,758 @@
+use std::path::PathBuf;
+use std::slice;
+use std::thread;
+use std::time::Duration;
+
 use sdl2;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::rect::Rect;
-use std::time::Instant;
+use sdl2::render::{BlendMode, Texture};
+use sdl2::video::FullscreenType;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 
 use cpu::{Cpu, Display};
+use interface::{Frontend, Input, Keymap, help_lines};
+use interface::filter::{Persistence, PersistenceFilter};
+use interface::palette::Palette;
+use interface::launcher::{Launcher, LauncherInput, LauncherState};
+use interface::memory::{self, Highlight, MemoryInput, MemoryView};
+use interface::sprites::{self, SpriteInput, SpriteView};
+use interface::text::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use rom::Rom;
+
+/// Default size of a CHIP-8 pixel in screen pixels
+pub const DEFAULT_SCALE: u32 = 10;
+
+const BEEP_FREQUENCY: f32 = 440.0;
+const BEEP_VOLUME: f32 = 0.1;
+
+/// Size of a pixel of the help text in screen pixels
+const TEXT_SCALE: u32 = 2;
+/// Size of a sprite pixel in the sprite view in screen pixels
+const SPRITE_SCALE: u32 = 4;
+/// Gap around the help text in screen pixels
+const TEXT_MARGIN: u32 = 8;
+
+/// How the display is scaled up to fill the window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    /// Every CHIP-8 pixel is the same whole number of screen pixels
+    Integer,
+    /// Fill as much of the window as possible, pixels may differ in size by one screen pixel
+    Fractional
+}
+
+impl Scaling {
+    pub fn from_name(name: &str) -> Option<Scaling> {
+        match name {
+            "integer" => Some(Scaling::Integer),
+            "fractional" => Some(Scaling::Fractional),
+            _ => None
+        }
+    }
+}
+
+/// Lines drawn over the display to make it look more like a real screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overlay {
+    None,
+    /// Darken every other line of the window, like a CRT
+    Scanlines,
+    /// Lines between the CHIP-8 pixels, like an LCD
+    Grid
+}
+
+impl Overlay {
+    pub const NAMES: &'static [&'static str] = &["none", "scanlines", "grid"];
+
+    pub fn from_name(name: &str) -> Option<Overlay> {
+        match name {
+            "none" => Some(Overlay::None),
+            "scanlines" => Some(Overlay::Scanlines),
+            "grid" => Some(Overlay::Grid),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Overlay::None => "none",
+            Overlay::Scanlines => "scanlines",
+            Overlay::Grid => "grid"
+        }
+    }
+
+    pub fn next(&self) -> Overlay {
+        match *self {
+            Overlay::None => Overlay::Scanlines,
+            Overlay::Scanlines => Overlay::Grid,
+            Overlay::Grid => Overlay::None
+        }
+    }
+}
+
+/// Settings for the SDL window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SdlOptions {
+    /// Size of a CHIP-8 pixel in screen pixels when the window opens
+    pub scale: u32,
+    pub scaling: Scaling,
+    pub fullscreen: bool,
+    pub palette: Palette,
+    pub persistence: Persistence,
+    pub overlay: Overlay
+}
+
+impl SdlOptions {
+    pub fn new() -> SdlOptions {
+        SdlOptions {
+            scale: DEFAULT_SCALE, scaling: Scaling::Integer, fullscreen: false,
+            palette: Palette::new(), persistence: Persistence::Off, overlay: Overlay::None
+        }
+    }
+}
+
+impl Default for SdlOptions {
+    fn default() -> SdlOptions {
+        SdlOptions::new()
+    }
+}
+
+/// The largest area of a `window` sized window that a `width` by `height` display can be drawn in
+/// while keeping its aspect ratio, centred so the rest of the window is an even border
+pub fn fit(window: (u32, u32), width: u32, height: u32, scaling: Scaling) -> Rect {
+    let (window_width, window_height) = window;
+    let (w, h) = match scaling {
+        Scaling::Integer => {
+            let scale = (window_width / width).min(window_height / height).max(1);
+            (width * scale, height * scale)
+        },
+        Scaling::Fractional => {
+            let scale = (window_width as f32 / width as f32).min(window_height as f32 / height as f32);
+            (((width as f32 * scale) as u32).max(1), ((height as f32 * scale) as u32).max(1))
+        }
+    };
+    Rect::new((window_width as i32 - w as i32) / 2, (window_height as i32 - h as i32) / 2, w, h)
+}
+
+/// Columns and rows of tiles used to show `count` displays at once
+pub fn tile_grid(count: usize) -> (u32, u32) {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    (columns, (count as u32).div_ceil(columns).max(1))
+}
+
+/// Where to draw each of the displays, sized `displays`, side by side in a `window` sized window
+pub fn tile_areas(window: (u32, u32), displays: &[(u32, u32)], scaling: Scaling) -> Vec<Rect> {
+    let (columns, rows) = tile_grid(displays.len());
+    let (width, height) = (window.0 / columns, window.1 / rows);
+    (0..displays.len() as u32).zip(displays).map(|(i, &(display_width, display_height))| {
+        let area = fit((width, height), display_width, display_height, scaling);
+        let (x, y) = ((i % columns * width) as i32, (i / columns * height) as i32);
+        Rect::new(area.x() + x, area.y() + y, area.width(), area.height())
+    }).collect()
+}
+
+/// The colour `brightness` of the way from `background` to `foreground`
+fn blend(background: Color, foreground: Color, brightness: f32) -> Color {
+    let mix = |b: u8, f: u8| (b as f32 + (f as f32 - b as f32) * brightness).round() as u8;
+    Color::RGB(mix(background.r, foreground.r), mix(background.g, foreground.g), mix(background.b, foreground.b))
+}
+
+/// Fill `pixels`, RGB24 rows `pitch` bytes apart, with the colours for the brightness given
+/// by a `PersistenceFilter`, `width` pixels per row
+fn fill_pixels(brightness: &[f32], width: usize, palette: Palette, pixels: &mut [u8], pitch: usize) {
+    for (i, &b) in brightness.iter().enumerate() {
+        let colour = blend(palette.background, palette.foreground, b);
+        let offset = (i / width) * pitch + (i % width) * 3;
+        pixels[offset..offset + 3].copy_from_slice(&[colour.r, colour.g, colour.b]);
+    }
+}
+
+/// The rectangles to darken for `overlay` when a `display` sized display is drawn in `area`
+fn overlay_rects(overlay: Overlay, area: Rect, display: (u32, u32)) -> Vec<Rect> {
+    let (width, height) = display;
+    match overlay {
+        Overlay::None => Vec::new(),
+        Overlay::Scanlines => (1..area.height() as i32).step_by(2)
+            .map(|y| Rect::new(area.x(), area.y() + y, area.width(), 1))
+            .collect(),
+        Overlay::Grid => {
+            // Only worth drawing when pixels are big enough to still be seen around the lines
+            if area.width() < width * 3 {
+                return Vec::new();
+            }
+            let columns = (1..width).map(|x| Rect::new(area.x() + (x * area.width() / width) as i32, area.y(), 1, area.height()));
+            let rows = (1..height).map(|y| Rect::new(area.x(), area.y() + (y * area.height() / height) as i32, area.width(), 1));
+            columns.chain(rows).collect()
+        }
+    }
+}
+
+/// A texture to upload a display of `display`'s size to, a texel per pixel
+fn screen_texture(canvas: &sdl2::render::WindowCanvas, display: &Display) -> Texture {
+    canvas.texture_creator()
+        .create_texture_streaming(PixelFormatEnum::RGB24, display.width() as u32, display.height() as u32)
+        .expect("could not create a texture for the display")
+}
+
+/// Upload the display to `texture`, which is the display's size, and stretch it over `area`, then
+/// draw the overlay on top
+fn draw_screen(brightness: &[f32], canvas: &mut sdl2::render::WindowCanvas, texture: &mut Texture, area: Rect,
+               palette: Palette, overlay: Overlay) {
+    let query = texture.query();
+    let width = query.width as usize;
+    texture.with_lock(None, |pixels, pitch| fill_pixels(brightness, width, palette, pixels, pitch)).unwrap();
+    canvas.copy(texture, None, area).unwrap();
+
+    let rects = overlay_rects(overlay, area, (query.width, query.height));
+    if !rects.is_empty() {
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(palette.background.r, palette.background.g, palette.background.b, 112));
+        canvas.fill_rects(&rects).unwrap();
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+fn draw_text(canvas: &mut sdl2::render::WindowCanvas, s: &str, x: i32, y: i32, colour: Color) {
+    draw_text_scaled(canvas, s, x, y, colour, TEXT_SCALE);
+}
+
+/// Draw `s` with each pixel of the font `scale` screen pixels across
+fn draw_text_scaled(canvas: &mut sdl2::render::WindowCanvas, s: &str, x: i32, y: i32, colour: Color, scale: u32) {
+    canvas.set_draw_color(colour);
+    let advance = ((GLYPH_WIDTH + 1) * scale) as i32;
+
+    for (n, c) in s.chars().enumerate() {
+        let glyph = text::glyph(c);
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    canvas.fill_rect(Rect::new(x + n as i32 * advance + (column * scale) as i32,
+                                               y + (row as u32 * scale) as i32,
+                                               scale, scale)).unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// Darken the screen and draw the help text on top of it
+fn draw_help(canvas: &mut sdl2::render::WindowCanvas, help: &[String], colour: Color) {
+    let (width, height) = canvas.output_size().unwrap_or((64 * DEFAULT_SCALE, 32 * DEFAULT_SCALE));
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 200));
+    canvas.fill_rect(None).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+
+    let columns = (width.saturating_sub(2 * TEXT_MARGIN) / ((GLYPH_WIDTH + 1) * TEXT_SCALE)).max(1) as usize;
+    let line_height = ((GLYPH_HEIGHT + 2) * TEXT_SCALE) as i32;
+    let mut y = TEXT_MARGIN as i32;
+
+    let hint = ["".to_string(), "Press F1 to close".to_string()];
+    for paragraph in help.iter().chain(hint.iter()) {
+        let lines = if paragraph.is_empty() { vec![String::new()] } else { text::wrap(paragraph, columns) };
+        for line in lines {
+            if y + line_height > height as i32 {
+                return;
+            }
+            draw_text(canvas, &line, TEXT_MARGIN as i32, y, colour);
+            y += line_height;
+        }
+    }
+}
+
+/// Text colour for the memory view, and the colour behind it if it has one
+fn highlight_colours(highlight: Highlight) -> (Color, Option<Color>) {
+    match highlight {
+        Highlight::None => (Color::RGB(255, 255, 255), None),
+        Highlight::Written => (Color::RGB(255, 200, 0), None),
+        Highlight::Sprite => (Color::RGB(80, 220, 80), None),
+        Highlight::I => (Color::RGB(0, 200, 255), None),
+        Highlight::Pc => (Color::RGB(255, 90, 90), None),
+        Highlight::Cursor => (Color::RGB(0, 0, 0), Some(Color::RGB(255, 255, 255))),
+        Highlight::Entry => (Color::RGB(0, 0, 0), Some(Color::RGB(255, 200, 0))),
+        Highlight::Candidate => (Color::RGB(230, 110, 255), None),
+        Highlight::Label => (Color::RGB(150, 150, 150), None)
+    }
+}
 
-const SCREEN_WIDTH: u32 = 640;
-const SCREEN_HEIGHT: u32 = 320;
-
-const CELL_WIDTH: u32 = SCREEN_WIDTH / 64;
-const CELL_HEIGHT: u32 = SCREEN_HEIGHT / 32;
-
-fn draw_screen(display: &Display, canvas: &mut sdl2::render::WindowCanvas) {
-    for (y, row) in display.pixels.iter().enumerate() {
-        for i in (0..64).rev() {
-            let mask = 1 << i;
-            let filled = (row & mask) >> i != 0;
-            if filled {
-                canvas.set_draw_color(Color::RGB(255, 255, 255));
-                canvas.fill_rect(Rect::new((63 - i) as i32 * CELL_WIDTH as i32, y as i32 * CELL_HEIGHT as i32,
-                                           CELL_WIDTH, CELL_HEIGHT)).unwrap();
+/// Darken the screen and draw the memory view on top of it, as big as fits
+fn draw_memory(canvas: &mut sdl2::render::WindowCanvas, view: &MemoryView, cpu: &Cpu) {
+    let (width, height) = canvas.output_size().unwrap_or((64 * DEFAULT_SCALE, 32 * DEFAULT_SCALE));
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 220));
+    canvas.fill_rect(None).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+
+    let columns = (memory::BYTES_PER_ROW * 3 + 5) as u32;
+    let scale = (width.saturating_sub(2 * TEXT_MARGIN) / ((GLYPH_WIDTH + 1) * columns))
+        .min(height.saturating_sub(2 * TEXT_MARGIN) / ((GLYPH_HEIGHT + 2) * memory::LINES as u32))
+        .clamp(1, TEXT_SCALE);
+    let (advance, line_height) = ((GLYPH_WIDTH + 1) * scale, (GLYPH_HEIGHT + 2) * scale);
+
+    for (row, line) in view.lines(cpu).iter().enumerate() {
+        let y = (TEXT_MARGIN + row as u32 * line_height) as i32;
+        let mut x = TEXT_MARGIN as i32;
+        for span in line {
+            let (colour, background) = highlight_colours(span.highlight);
+            let span_width = span.text.chars().count() as u32 * advance;
+            if let Some(background) = background {
+                canvas.set_draw_color(background);
+                canvas.fill_rect(Rect::new(x - scale as i32, y - scale as i32, span_width, line_height)).unwrap();
             }
+            draw_text_scaled(canvas, &span.text, x, y, colour, scale);
+            x += span_width as i32;
         }
     }
 }
 
-pub fn run(filepath: Option<&str>, _debug_mode: bool) {
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+/// Darken the screen and draw the sprite view on top of it
+fn draw_sprites(canvas: &mut sdl2::render::WindowCanvas, view: &SpriteView, cpu: &Cpu, colour: Color) {
+    let (width, height) = canvas.output_size().unwrap_or((64 * DEFAULT_SCALE, 32 * DEFAULT_SCALE));
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 220));
+    canvas.fill_rect(None).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+
+    let line_height = ((GLYPH_HEIGHT + 2) * TEXT_SCALE) as i32;
+    let margin = TEXT_MARGIN as i32;
+    draw_text(canvas, &view.heading(), margin, margin, colour);
 
-    let window = video_subsystem.window("rust-chip8", SCREEN_WIDTH, SCREEN_HEIGHT)
-        .position_centered()
-        .build()
-        .unwrap();
+    let count = view.strip_count((width.saturating_sub(2 * TEXT_MARGIN) / SPRITE_SCALE) as usize);
+    let strip = view.strip(cpu, count);
+    let (top, scale) = (margin + line_height, SPRITE_SCALE as i32);
+    // A grey box behind each sprite to show where it starts and ends
+    let cell = (strip.width as i32 + 1) / count as i32;
+    canvas.set_draw_color(Color::RGB(48, 48, 48));
+    for i in 0..count as i32 {
+        canvas.fill_rect(Rect::new(margin + i * cell * scale, top, (cell - 1) as u32 * SPRITE_SCALE,
+                                   strip.height as u32 * SPRITE_SCALE)).unwrap();
+    }
+    canvas.set_draw_color(colour);
+    for y in 0..strip.height {
+        for x in (0..strip.width).filter(|&x| strip.get(x, y)) {
+            canvas.fill_rect(Rect::new(margin + x as i32 * scale, top + y as i32 * scale, SPRITE_SCALE, SPRITE_SCALE))
+                .unwrap();
+        }
+    }
+
+    let columns = (width.saturating_sub(2 * TEXT_MARGIN) / ((GLYPH_WIDTH + 1) * TEXT_SCALE)).max(1) as usize;
+    let mut y = top + strip.height as i32 * scale + line_height;
+    for line in view.lines().iter().flat_map(|line| text::wrap(line, columns)) {
+        if y + line_height > height as i32 {
+            return;
+        }
+        draw_text(canvas, &line, margin, y, colour);
+        y += line_height;
+    }
+}
+
+/// What a key does in the sprite view, if anything
+fn keycode_to_sprite_input(keycode: Keycode) -> Option<SpriteInput> {
+    let name = keycode.name();
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => sprites::sprite_input(c.to_ascii_lowercase()),
+        _ => None
+    }
+}
 
-    let mut canvas = window.into_canvas().build().unwrap();
+/// What a key does in the memory view, if anything. Hex digits are only taken while paused,
+/// otherwise they're left for the keypad.
+fn keycode_to_memory_input(keycode: Keycode, paused: bool) -> Option<MemoryInput> {
+    match keycode {
+        Keycode::Up | Keycode::K => Some(MemoryInput::Up),
+        Keycode::Down | Keycode::J => Some(MemoryInput::Down),
+        Keycode::Left | Keycode::H => Some(MemoryInput::Left),
+        Keycode::Right | Keycode::L => Some(MemoryInput::Right),
+        Keycode::PageUp | Keycode::Less => Some(MemoryInput::PageUp),
+        Keycode::PageDown | Keycode::Greater => Some(MemoryInput::PageDown),
+        Keycode::Tab => Some(MemoryInput::SwitchFocus),
+        Keycode::I => Some(MemoryInput::GoToI),
+        Keycode::P => Some(MemoryInput::GoToPc),
+        Keycode::Space => Some(MemoryInput::Pause),
+        Keycode::Return | Keycode::KpEnter => Some(MemoryInput::Enter),
+        Keycode::Backspace => Some(MemoryInput::Cancel),
+        _ if paused => {
+            let name = keycode.name();
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c.to_digit(16).map(|digit| MemoryInput::Digit(digit as u8)),
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
 
-    canvas.clear();
-    canvas.present();
-    let mut event_pump = sdl_context.event_pump().unwrap();
+/// Let the user pick a ROM from `launcher` in a window. None if they close it instead.
+pub fn choose_rom(launcher: &mut Launcher, options: &SdlOptions) -> Result<Option<PathBuf>, String> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let mut canvas = open_window(&video_subsystem, "rust-chip8", options)?;
+    let palette = options.palette;
+    let mut event_pump = sdl_context.event_pump()?;
+    video_subsystem.text_input().start();
 
-    let (mut cpu, _) = Cpu::from_program_file(filepath);
+    let line_height = (GLYPH_HEIGHT + 2) * TEXT_SCALE;
+    let mut state = LauncherState::Browsing;
 
-    let mut cpu_timer = Instant::now();
-    let mut sdl_timer = Instant::now();
+    while state == LauncherState::Browsing {
+        let (width, height) = canvas.output_size()?;
+        let columns = width.saturating_sub(2 * TEXT_MARGIN) / ((GLYPH_WIDTH + 1) * TEXT_SCALE);
+        let (lines, highlight) = launcher.screen_lines(columns as usize, (height.saturating_sub(TEXT_MARGIN) / line_height) as usize);
 
-    'running: loop {
-        if sdl_timer.elapsed().subsec_nanos() > 1_000_000_000u32 / 60 {
-            canvas.set_draw_color(Color::RGB(0, 0, 0));
-            canvas.clear();
+        canvas.set_draw_color(palette.background);
+        canvas.clear();
+        for (i, line) in lines.iter().enumerate() {
+            let y = (TEXT_MARGIN + i as u32 * line_height) as i32;
+            if highlight == Some(i) {
+                canvas.set_draw_color(palette.foreground);
+                canvas.fill_rect(Rect::new(0, y - TEXT_SCALE as i32, width, line_height)).unwrap();
+                draw_text(&mut canvas, line, TEXT_MARGIN as i32, y, palette.background);
+            } else {
+                draw_text(&mut canvas, line, TEXT_MARGIN as i32, y, palette.foreground);
+            }
+        }
+        canvas.present();
 
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit { .. } |
-                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                        break 'running
-                    },
-                    _ => {}
+        for event in event_pump.poll_iter() {
+            let input = match event {
+                Event::Quit { .. } => Some(LauncherInput::Cancel),
+                Event::TextInput { ref text, .. } => {
+                    for c in text.chars() {
+                        launcher.handle(LauncherInput::Char(c));
+                    }
+                    None
+                },
+                Event::KeyDown { keycode: Some(keycode), .. } => match keycode {
+                    Keycode::Escape => Some(LauncherInput::Cancel),
+                    Keycode::Return | Keycode::KpEnter => Some(LauncherInput::Select),
+                    Keycode::Up => Some(LauncherInput::Up),
+                    Keycode::Down => Some(LauncherInput::Down),
+                    Keycode::PageUp => Some(LauncherInput::PageUp),
+                    Keycode::PageDown => Some(LauncherInput::PageDown),
+                    Keycode::Backspace => Some(LauncherInput::Backspace),
+                    _ => None
+                },
+                _ => None
+            };
+            if let Some(input) = input {
+                state = launcher.handle(input);
+                if state != LauncherState::Browsing {
+                    break;
                 }
             }
+        }
+
+        thread::sleep(Duration::from_millis(16));
+    }
+
+    video_subsystem.text_input().stop();
+    match state {
+        LauncherState::Chosen(path) => Ok(Some(path)),
+        _ => Ok(None)
+    }
+}
+
+/// Map an SDL keycode to a key on the CHIP-8 keypad
+fn keycode_to_key(keymap: &Keymap, keycode: Keycode) -> Option<u8> {
+    let name = keycode.name();
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => keymap.keypad_key(c),
+        _ => None
+    }
+}
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = if self.phase <= 0.5 { self.volume } else { -self.volume };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+/// Draws the display in a window and plays the beep through the speakers
+pub struct SdlFrontend {
+    canvas: sdl2::render::WindowCanvas,
+    event_pump: sdl2::EventPump,
+    // None if the audio device couldn't be opened, the emulator still runs without sound
+    audio: Option<AudioDevice<SquareWave>>,
+    keymap: Keymap,
+    // A texture holding each display, one texel per CHIP-8 pixel, and its filter
+    screens: Vec<(Texture, PersistenceFilter)>,
+    // Shown over each display when there's more than one
+    labels: Vec<String>,
+    scale: u32,
+    scaling: Scaling,
+    palette: Palette,
+    overlay: Overlay,
+    help: Vec<String>,
+    show_help: bool,
+    memory: MemoryView,
+    show_memory: bool,
+    sprites: SpriteView,
+    show_sprites: bool,
+    persistence: Persistence,
+    title: String,
+    quit: bool
+}
+
+fn open_window(video_subsystem: &sdl2::VideoSubsystem, title: &str, options: &SdlOptions)
+               -> Result<sdl2::render::WindowCanvas, String> {
+    let mut builder = video_subsystem.window(title, 64 * options.scale, 32 * options.scale);
+    builder.position_centered().resizable();
+    if options.fullscreen {
+        builder.fullscreen_desktop();
+    }
+    let window = builder.build().map_err(|e| e.to_string())?;
+    window.into_canvas().build().map_err(|e| e.to_string())
+}
+
+impl SdlFrontend {
+    pub fn new(rom: &Rom, keymap: Keymap, options: &SdlOptions) -> Result<SdlFrontend, String> {
+        let sdl_context = sdl2::init()?;
+        let video_subsystem = sdl_context.video()?;
+
+        let title = format!("rust-chip8 - {}", rom.title);
+        let mut canvas = open_window(&video_subsystem, &title, options)?;
+        canvas.clear();
+        canvas.present();
+        let event_pump = sdl_context.event_pump()?;
+        // For the memory view's search keys
+        video_subsystem.text_input().start();
+
+        let spec = AudioSpecDesired { freq: Some(44100), channels: Some(1), samples: None };
+        let audio = sdl_context.audio().and_then(|audio| {
+            audio.open_playback(None, &spec, |spec| SquareWave {
+                phase_inc: BEEP_FREQUENCY / spec.freq as f32,
+                phase: 0.0,
+                volume: BEEP_VOLUME
+            })
+        }).ok();
+
+        Ok(SdlFrontend {
+            canvas, event_pump, audio, keymap, screens: Vec::new(), labels: Vec::new(), scale: options.scale,
+            scaling: options.scaling, palette: options.palette, overlay: options.overlay,
+            help: help_lines(rom), show_help: false, memory: MemoryView::new(), show_memory: false,
+            sprites: SpriteView::new(sprites::sheet_path(rom)), show_sprites: false, persistence: options.persistence,
+            title, quit: false
+        })
+    }
+
+    /// Show a display for each of `labels` side by side, for running several machines at once with
+    /// `render_many`. The window is made big enough for all of them.
+    pub fn tile(&mut self, labels: Vec<String>) {
+        let (columns, rows) = tile_grid(labels.len());
+        let _ = self.canvas.window_mut().set_size(64 * self.scale * columns, 32 * self.scale * rows);
+        self.labels = labels;
+    }
+}
+
+impl Frontend for SdlFrontend {
+    fn render(&mut self, cpu: &Cpu) {
+        self.render_many(slice::from_ref(cpu));
+    }
+
+    fn render_many(&mut self, cpus: &[Cpu]) {
+        self.canvas.set_draw_color(self.palette.background);
+        self.canvas.clear();
+
+        while self.screens.len() < cpus.len() {
+            let texture = screen_texture(&self.canvas, &cpus[self.screens.len()].display);
+            self.screens.push((texture, PersistenceFilter::new(self.persistence)));
+        }
+        for (cpu, &mut (ref mut texture, _)) in cpus.iter().zip(&mut self.screens) {
+            let query = texture.query();
+            if (query.width as usize, query.height as usize) != (cpu.display.width(), cpu.display.height()) {
+                *texture = screen_texture(&self.canvas, &cpu.display);
+            }
+        }
+
+        let sizes: Vec<(u32, u32)> = cpus.iter()
+            .map(|cpu| (cpu.display.width() as u32, cpu.display.height() as u32))
+            .collect();
+        let areas = tile_areas(self.canvas.output_size().unwrap_or((64, 32)), &sizes, self.scaling);
+        for ((cpu, &area), &mut (ref mut texture, ref mut filter)) in cpus.iter().zip(&areas).zip(&mut self.screens) {
+            filter.mode = self.persistence;
+            let brightness = filter.apply(&cpu.display);
+            draw_screen(brightness, &mut self.canvas, texture, area, self.palette, self.overlay);
+        }
+        if cpus.len() > 1 {
+            for (label, area) in self.labels.iter().zip(&areas) {
+                draw_text(&mut self.canvas, label, area.x() + 4, area.y() + 4, self.palette.plane_2);
+            }
+        }
+
+        // Only the first machine's memory is shown
+        self.memory.update(&cpus[0]);
+        self.sprites.update(&cpus[0]);
+        if self.show_memory {
+            draw_memory(&mut self.canvas, &self.memory, &cpus[0]);
+        }
+        if self.show_sprites {
+            draw_sprites(&mut self.canvas, &self.sprites, &cpus[0], Color::RGB(255, 255, 255));
+        }
+        if self.show_help {
+            draw_help(&mut self.canvas, &self.help, Color::RGB(255, 255, 255));
+        }
+        self.canvas.present();
+    }
+
+    fn poll_input(&mut self) -> Vec<Input> {
+        let mut inputs = Vec::new();
+
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => self.quit = true,
+                Event::KeyDown { keycode: Some(Keycode::F1), repeat: false, .. } => self.show_help = !self.show_help,
+                Event::KeyDown { keycode: Some(Keycode::F4), repeat: false, .. } => {
+                    self.show_memory = !self.show_memory;
+                    self.show_sprites = false;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F7), repeat: false, .. } => {
+                    self.show_sprites = !self.show_sprites;
+                    self.show_memory = false;
+                },
+                Event::KeyDown { keycode: Some(keycode), .. }
+                    if self.show_sprites && keycode_to_sprite_input(keycode).is_some() => {
+                    self.sprites.handle(keycode_to_sprite_input(keycode).unwrap());
+                },
+                // Typed characters for the RAM search, since most of them need shift
+                Event::TextInput { ref text, .. } if self.show_memory => {
+                    for input in text.chars().filter_map(memory::search_input) {
+                        inputs.extend(self.memory.handle(input));
+                    }
+                },
+                Event::KeyDown { keycode: Some(keycode), .. }
+                    if self.show_memory && keycode_to_memory_input(keycode, self.memory.paused()).is_some() => {
+                    let input = keycode_to_memory_input(keycode, self.memory.paused()).unwrap();
+                    inputs.extend(self.memory.handle(input));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => inputs.push(Input::Reset),
+                Event::KeyDown { keycode: Some(Keycode::F6), repeat: false, .. } => inputs.push(Input::Reload),
+                Event::KeyDown { keycode: Some(Keycode::F3), repeat: false, .. } => {
+                    self.overlay = self.overlay.next();
+                    let title = format!("{} (overlay: {})", self.title, self.overlay.name());
+                    let _ = self.canvas.window_mut().set_title(&title);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                    let window = self.canvas.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off
+                    };
+                    let _ = window.set_fullscreen(fullscreen);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => {
+                    self.persistence = self.persistence.next();
+                    let title = format!("{} (persistence: {})", self.title, self.persistence.name());
+                    let _ = self.canvas.window_mut().set_title(&title);
+                },
+                Event::KeyDown { keycode: Some(keycode), repeat: false, .. } => {
+                    if let Some(key) = keycode_to_key(&self.keymap, keycode) {
+                        inputs.push(Input::KeyDown(key));
+                    }
+                },
+                Event::KeyUp { keycode: Some(keycode), .. } => {
+                    if let Some(key) = keycode_to_key(&self.keymap, keycode) {
+                        inputs.push(Input::KeyUp(key));
+                    }
+                },
+                _ => {}
+            }
+        }
 
-            draw_screen(&cpu.display, &mut canvas);
-            canvas.present();
+        inputs
+    }
 
-            sdl_timer = Instant::now();
+    fn play_audio(&mut self, beeping: bool) {
+        if let Some(ref audio) = self.audio {
+            if beeping {
+                audio.resume();
+            } else {
+                audio.pause();
+            }
         }
+    }
+
+    fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    fn paused(&self) -> bool {
+        self.show_memory && self.memory.paused()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_for_texture() {
+        let mut brightness = vec![0.0; 64 * 32];
+        brightness[0] = 1.0;
+        brightness[64 + 1] = 0.5;
+        let palette = Palette::from_colours(Color::RGB(200, 100, 0), Color::RGB(0, 0, 10));
+        let pitch = 64 * 3 + 8;
+        let mut pixels = vec![0xAA; pitch * 32];
+        fill_pixels(&brightness, 64, palette, &mut pixels, pitch);
+
+        assert_eq!(&pixels[0..6], &[200, 100, 0, 0, 0, 10]);
+        assert_eq!(&pixels[pitch + 3..pitch + 6], &[100, 50, 5]);
+        // Padding at the end of each row is left alone
+        assert_eq!(pixels[pitch - 1], 0xAA);
+    }
 
-        if cpu_timer.elapsed().subsec_nanos() > 1_000_000_000u32 / 540 {
-            cpu.tick();
-            cpu_timer = Instant::now();
+    #[test]
+    fn overlays() {
+        let area = Rect::new(0, 0, 640, 320);
+        assert!(overlay_rects(Overlay::None, area, (64, 32)).is_empty());
+        assert_eq!(overlay_rects(Overlay::Scanlines, area, (64, 32)).len(), 160);
+        assert_eq!(overlay_rects(Overlay::Grid, area, (64, 32)).len(), 63 + 31);
+        assert_eq!(overlay_rects(Overlay::Grid, area, (128, 64)).len(), 127 + 63);
+        assert_eq!(overlay_rects(Overlay::Grid, Rect::new(0, 0, 128, 64), (64, 32)).len(), 0);
+        assert_eq!(overlay_rects(Overlay::Grid, Rect::new(0, 0, 320, 160), (128, 64)).len(), 0);
+        for &name in Overlay::NAMES {
+            assert_eq!(Overlay::from_name(name).unwrap().name(), name);
         }
     }
+
+    #[test]
+    fn fit_keeps_aspect_ratio() {
+        assert_eq!(fit((640, 320), 64, 32, Scaling::Integer), Rect::new(0, 0, 640, 320));
+        assert_eq!(fit((800, 600), 64, 32, Scaling::Integer), Rect::new(16, 108, 768, 384));
+        assert_eq!(fit((800, 600), 64, 32, Scaling::Fractional), Rect::new(0, 100, 800, 400));
+        assert_eq!(fit((1000, 200), 64, 32, Scaling::Fractional), Rect::new(300, 0, 400, 200));
+        assert_eq!(fit((10, 10), 64, 32, Scaling::Integer), Rect::new(-27, -11, 64, 32));
+    }
+
+    #[test]
+    fn tiles() {
+        assert_eq!(tile_grid(1), (1, 1));
+        assert_eq!(tile_grid(3), (2, 2));
+        assert_eq!(tile_grid(5), (3, 2));
+        assert_eq!(tile_areas((1280, 320), &[(64, 32), (64, 32)], Scaling::Integer),
+                   vec![Rect::new(0, 0, 640, 320), Rect::new(640, 0, 640, 320)]);
+        assert_eq!(tile_areas((1280, 640), &[(64, 32); 3], Scaling::Integer)[2], Rect::new(0, 320, 640, 320));
+        assert_eq!(tile_areas((1200, 300), &[(64, 32), (128, 64)], Scaling::Integer),
+                   vec![Rect::new(12, 6, 576, 288), Rect::new(644, 22, 512, 256)]);
+    }
 }
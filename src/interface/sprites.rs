@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::PathBuf;
+
+use cpu::Cpu;
+use rom::Rom;
+use sprites::{self, Bitmap, SpriteSize};
+
+/// Sprites a row in the exported sprite sheet
+const SHEET_COLUMNS: usize = 8;
+
+/// What the user did in the sprite view, mapped from whatever keys the frontend has
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpriteInput {
+    /// A byte back
+    Back,
+    /// A byte on
+    Forward,
+    PreviousSprite,
+    NextSprite,
+    /// Show the sprite at I, keeping up as it changes
+    FollowI,
+    Taller,
+    Shorter,
+    /// Switch between 8xN and 16x16 sprites
+    ToggleLarge,
+    /// Go to the next address a sprite has been drawn from
+    NextDrawn,
+    /// Write the sprites drawn so far to an image
+    Export
+}
+
+/// What a key does in the sprite view, if anything
+pub fn sprite_input(key: char) -> Option<SpriteInput> {
+    match key {
+        'h' => Some(SpriteInput::Back),
+        'l' => Some(SpriteInput::Forward),
+        'k' => Some(SpriteInput::PreviousSprite),
+        'j' => Some(SpriteInput::NextSprite),
+        'i' => Some(SpriteInput::FollowI),
+        '=' | '+' => Some(SpriteInput::Taller),
+        '-' => Some(SpriteInput::Shorter),
+        'b' => Some(SpriteInput::ToggleLarge),
+        'g' => Some(SpriteInput::NextDrawn),
+        'o' => Some(SpriteInput::Export),
+        _ => None
+    }
+}
+
+/// Where the sprite sheet for `rom` goes: next to it, or in the current directory if it has no path
+pub fn sheet_path(rom: &Rom) -> PathBuf {
+    match rom.path {
+        Some(ref path) => path.with_extension("sprites.pbm"),
+        None => PathBuf::from("sprites.pbm")
+    }
+}
+
+/// Memory drawn as sprites, starting at I or wherever the user moves to, and the addresses sprites
+/// have been drawn from. Frontends call `update` every frame and draw `strip` and `lines`, and pass
+/// key presses to `handle`.
+pub struct SpriteView {
+    addr: u16,
+    size: SpriteSize,
+    follow: bool,
+    // As they were last frame, for `handle`
+    drawn: Vec<(u16, SpriteSize)>,
+    memory_size: usize,
+    export_path: PathBuf,
+    export: bool,
+    // The result of the last export
+    message: Option<String>
+}
+
+impl SpriteView {
+    /// A view that writes its sprite sheet to `export_path`
+    pub fn new(export_path: PathBuf) -> SpriteView {
+        SpriteView {
+            addr: 0, size: SpriteSize::Small(8), follow: true, drawn: Vec::new(), memory_size: 0,
+            export_path, export: false, message: None
+        }
+    }
+
+    pub fn update(&mut self, cpu: &Cpu) {
+        self.drawn = cpu.drawn_sprites.iter().map(|(&addr, &size)| (addr, size)).collect();
+        self.memory_size = cpu.memory.len();
+        if self.follow {
+            self.addr = cpu.i_reg;
+            // Draw it the way the program does when it can be told
+            if let Some((addr, n)) = cpu.last_sprite {
+                if addr == self.addr {
+                    self.size = SpriteSize::from_nibble(n);
+                }
+            }
+        }
+        if self.export {
+            self.export = false;
+            self.message = Some(match self.write_sheet(cpu) {
+                Ok(()) => format!("wrote {}", self.export_path.display()),
+                Err(e) => e
+            });
+        }
+    }
+
+    fn write_sheet(&self, cpu: &Cpu) -> Result<(), String> {
+        if self.drawn.is_empty() {
+            return Err("no sprites drawn yet".to_string());
+        }
+        let sheet = sprites::sheet(&cpu.memory, &self.drawn, SHEET_COLUMNS);
+        fs::write(&self.export_path, sheet.to_pbm())
+            .map_err(|e| format!("could not write {}: {}", self.export_path.display(), e))
+    }
+
+    fn go_to(&mut self, addr: i32) {
+        self.addr = addr.max(0).min(self.memory_size as i32 - 1).max(0) as u16;
+        self.follow = false;
+    }
+
+    pub fn handle(&mut self, input: SpriteInput) {
+        let (addr, bytes) = (self.addr as i32, self.size.bytes() as i32);
+        match input {
+            SpriteInput::Back => self.go_to(addr - 1),
+            SpriteInput::Forward => self.go_to(addr + 1),
+            SpriteInput::PreviousSprite => self.go_to(addr - bytes),
+            SpriteInput::NextSprite => self.go_to(addr + bytes),
+            SpriteInput::FollowI => self.follow = true,
+            SpriteInput::Taller => if let SpriteSize::Small(rows) = self.size {
+                self.size = SpriteSize::Small((rows + 1).min(15));
+            },
+            SpriteInput::Shorter => if let SpriteSize::Small(rows) = self.size {
+                self.size = SpriteSize::Small((rows - 1).max(1));
+            },
+            SpriteInput::ToggleLarge => self.size = match self.size {
+                SpriteSize::Small(_) => SpriteSize::Large,
+                SpriteSize::Large => SpriteSize::Small(8)
+            },
+            SpriteInput::NextDrawn => {
+                let next = self.drawn.iter().find(|&&(addr, _)| addr > self.addr).or_else(|| self.drawn.first());
+                if let Some(&(addr, size)) = next {
+                    self.go_to(addr as i32);
+                    self.size = size;
+                }
+            },
+            SpriteInput::Export => self.export = true
+        }
+    }
+
+    /// `count` sprites one after another from the current address
+    pub fn strip(&self, cpu: &Cpu, count: usize) -> Bitmap {
+        sprites::sheet(&cpu.memory, &sprites::consecutive(self.addr, self.size, count), count)
+    }
+
+    /// Width in pixels of `strip` for `count` sprites
+    pub fn strip_width(&self, count: usize) -> usize {
+        (self.size.width() + 1) * count - 1
+    }
+
+    /// The sprites that fit in `width` pixels, at least one
+    pub fn strip_count(&self, width: usize) -> usize {
+        ((width + 1) / (self.size.width() + 1)).max(1)
+    }
+
+    /// What's being shown, to go above the strip
+    pub fn heading(&self) -> String {
+        let following = if self.follow { ", following I" } else { "" };
+        format!("{:03X}: {}x{}{}", self.addr, self.size.width(), self.size.height(), following)
+    }
+
+    /// Text to go below the strip: the addresses sprites were drawn from, a hint and how the last
+    /// export went
+    pub fn lines(&self) -> Vec<String> {
+        let drawn: Vec<String> = self.drawn.iter().map(|&(addr, _)| format!("{:03X}", addr)).collect();
+        let mut lines = vec![
+            format!("Drawn from: {}", if drawn.is_empty() { "nothing yet".to_string() } else { drawn.join(" ") }),
+            "hjkl move, i follow I, -/= rows, b 16x16, g next drawn, o save drawn".to_string()
+        ];
+        lines.extend(self.message.clone());
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite_cpu() -> Cpu {
+        // LD I, 0x20A / DRW V0, V0, 3 / LD I, 0x20D / DRW V0, V0, 2 / JP 0x208 / sprites
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&Rom::from_bytes(vec![0xA2, 0x0A, 0xD0, 0x03, 0xA2, 0x0D, 0xD0, 0x02, 0x12, 0x08,
+                                           0xF0, 0x90, 0xF0, 0x60, 0x60]).unwrap()).unwrap();
+        for _ in 0..3 {
+            cpu.tick();
+        }
+        cpu
+    }
+
+    #[test]
+    fn follows_i() {
+        let mut cpu = sprite_cpu();
+        let mut view = SpriteView::new(PathBuf::new());
+        view.update(&cpu);
+        // Nothing has been drawn from I yet, so there's no telling how big the sprite is
+        assert_eq!(view.heading(), "20D: 8x8, following I");
+        assert_eq!(view.lines()[0], "Drawn from: 20A");
+        cpu.tick();
+        view.update(&cpu);
+        assert_eq!(view.heading(), "20D: 8x2, following I");
+        assert_eq!(view.strip_count(64), 7);
+
+        let strip = view.strip(&cpu, 1);
+        assert_eq!((strip.width, strip.height), (8, 2));
+        assert!(strip.get(1, 0) && strip.get(2, 1) && !strip.get(0, 0));
+
+        view.handle(SpriteInput::PreviousSprite);
+        view.handle(SpriteInput::Taller);
+        assert_eq!(view.heading(), "20B: 8x3");
+        view.handle(SpriteInput::NextDrawn);
+        assert_eq!(view.heading(), "20D: 8x2");
+        view.handle(SpriteInput::NextDrawn);
+        assert_eq!(view.heading(), "20A: 8x3");
+        view.handle(SpriteInput::ToggleLarge);
+        assert_eq!(view.strip_width(2), 33);
+        view.handle(SpriteInput::FollowI);
+        view.update(&cpu);
+        assert_eq!(view.heading(), "20D: 8x2, following I");
+    }
+
+    #[test]
+    fn export() {
+        let path = ::std::env::temp_dir().join(format!("rust-chip8-sprites-test-{}.pbm", ::std::process::id()));
+        let mut cpu = sprite_cpu();
+        let mut view = SpriteView::new(path.clone());
+        cpu.tick();
+        view.update(&cpu);
+        view.handle(sprite_input('o').unwrap());
+        view.update(&cpu);
+        assert_eq!(view.lines()[2], format!("wrote {}", path.display()));
+
+        let mut expected = b"P4\n17 3\n".to_vec();
+        expected.extend_from_slice(&[0xF0, 0x30, 0x00, 0x90, 0x30, 0x00, 0xF0, 0x00, 0x00]);
+        assert_eq!(fs::read(&path).unwrap(), expected);
+        fs::remove_file(&path).unwrap();
+
+        let mut view = SpriteView::new(path);
+        view.handle(SpriteInput::Export);
+        view.update(&Cpu::new());
+        assert_eq!(view.lines()[2], "no sprites drawn yet");
+    }
+}
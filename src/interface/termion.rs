@@ -1,124 +1,458 @@
 use termion;
-use std::thread;
-use std::time::Duration;
 use std::io;
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use termion::raw::IntoRawMode;
-use termion::async_stdin;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::{async_stdin, AsyncReader};
 
 use cpu::{Cpu, Display};
+use interface::{Frontend, Input, Keymap, help_lines};
+use interface::cells::{CellMode, CellRenderer};
+use interface::keys::{self, KeyEventKind, KeyHold, KeyMode};
+use interface::launcher::{Launcher, LauncherInput, LauncherState};
+use interface::memory::{self, Highlight, MemoryInput, MemoryView};
+use interface::sprites::{self, SpriteView};
+use interface::text::wrap;
+use rom::Rom;
+use sprites::Bitmap;
 
-fn draw_screen(display: &Display) {
-    for (y, row) in display.pixels.iter().enumerate() {
+/// Rows needed by `print_debug_info`
+const DEBUG_ROWS: u16 = 24;
+/// How long to wait for the terminal to say whether it supports the kitty keyboard protocol
+const KITTY_QUERY_TIMEOUT: Duration = Duration::from_millis(200);
 
-        let mut display_row = String::new();
+/// Settings for the terminal frontend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TermionOptions {
+    pub debug: bool,
+    pub cells: CellMode,
+    pub keys: KeyMode,
+    /// How long a key stays held after the terminal sends it, when keys are in `KeyMode::Hold`
+    pub hold_delay: Duration
+}
 
-        for i in (0..64).rev() {
-            let mask = 1 << i;
-            let filled = (row & mask) >> i != 0;
-            if filled {
-                print!("{}", termion::cursor::Goto(1, (y + 1) as u16));
-                display_row += "x";
-            } else {
-                display_row += " ";
-            }
+impl TermionOptions {
+    pub fn new() -> TermionOptions {
+        TermionOptions { debug: false, cells: CellMode::HalfBlock, keys: KeyMode::Auto, hold_delay: keys::DEFAULT_HOLD_DELAY }
+    }
+}
+
+impl Default for TermionOptions {
+    fn default() -> TermionOptions {
+        TermionOptions::new()
+    }
+}
+
+/// Ask the terminal whether it supports the kitty keyboard protocol, giving up after a short wait
+fn detect_kitty(stdin: &mut io::Bytes<AsyncReader>, stdout: &mut RawTerminal<io::Stdout>) -> bool {
+    print!("{}", keys::KITTY_QUERY);
+    if stdout.flush().is_err() {
+        return false;
+    }
+
+    let start = Instant::now();
+    let mut reply = Vec::new();
+    while start.elapsed() < KITTY_QUERY_TIMEOUT {
+        match stdin.next() {
+            Some(Ok(b)) => {
+                reply.push(b);
+                if let Some(supported) = keys::kitty_supported(&reply) {
+                    // Skip the rest of the device attributes answer
+                    thread::sleep(Duration::from_millis(10));
+                    while let Some(Ok(_)) = stdin.next() {}
+                    return supported;
+                }
+            },
+            _ => thread::sleep(Duration::from_millis(5))
         }
+    }
+    false
+}
 
-        print!("{}", display_row);
+/// Write the cells that changed since the last frame, all at once to avoid tearing
+fn draw_screen(display: &Display, renderer: &mut CellRenderer) {
+    let mut out = String::new();
+    for change in renderer.changes(display) {
+        out += &format!("{}{}", termion::cursor::Goto(change.x as u16 + 1, change.y as u16 + 1), change.text);
     }
+    print!("{}", out);
 }
 
 fn print_debug_info(cpu: &Cpu, program_name: &str) {
+    let clear = termion::clear::UntilNewline;
     for (i, v) in cpu.v_reg.iter().enumerate() {
         print!("{}", termion::cursor::Goto(1, (i + 1) as u16));
-        print!("V{} = {:X}", i, v);
+        print!("V{} = {:X}{}", i, v, clear);
     }
 
     print!("{}", termion::cursor::Goto(1, 17));
-    print!("I = {:X}", cpu.i_reg);
+    print!("I = {:X}{}", cpu.i_reg, clear);
     print!("{}", termion::cursor::Goto(1, 18));
-    print!("PC = {:X}", cpu.prog_counter);
+    print!("PC = {:X}{}", cpu.prog_counter, clear);
     print!("{}", termion::cursor::Goto(1, 19));
-    print!("SP = {:X}", cpu.stack_pointer);
+    print!("SP = {:X}{}", cpu.stack_pointer, clear);
     print!("{}", termion::cursor::Goto(1, 20));
-    print!("prog name = {}", program_name);
+    print!("prog name = {}{}", program_name, clear);
 
-    print!("{}", termion::cursor::Goto(1, 24));
-    print!("press q to exit");
+    print!("{}", termion::cursor::Goto(1, DEBUG_ROWS));
+    print!("press ; to exit, ? for help");
 }
 
-pub fn run(filepath: Option<&str>, debug_mode: bool) {
-    let term_size = termion::terminal_size().unwrap();
-    if term_size.0 < 64 || term_size.1 < 32 {
-        eprintln!("window size needs to be at least 64x32");
-        return
+/// Colours for the memory view
+fn highlight_style(highlight: Highlight) -> String {
+    use termion::color::{Fg, Cyan, Green, LightRed, Magenta, Yellow};
+    match highlight {
+        Highlight::None => String::new(),
+        Highlight::Written => Fg(Yellow).to_string(),
+        Highlight::Sprite => Fg(Green).to_string(),
+        Highlight::I => Fg(Cyan).to_string(),
+        Highlight::Pc => Fg(LightRed).to_string(),
+        Highlight::Cursor => termion::style::Invert.to_string(),
+        Highlight::Entry => format!("{}{}", termion::style::Invert, termion::style::Bold),
+        Highlight::Candidate => Fg(Magenta).to_string(),
+        Highlight::Label => termion::style::Faint.to_string()
     }
+}
 
-    let (mut cpu, example_program) = Cpu::from_program_file(filepath);
+fn print_memory(view: &MemoryView, cpu: &Cpu) {
+    let mut out = String::new();
+    for (y, line) in view.lines(cpu).iter().enumerate() {
+        out += &termion::cursor::Goto(1, y as u16 + 1).to_string();
+        for span in line {
+            out += &format!("{}{}{}", highlight_style(span.highlight), span.text, termion::style::Reset);
+        }
+        out += &termion::clear::UntilNewline.to_string();
+    }
+    print!("{}", out);
+}
 
-    let stdout = io::stdout();
-    let mut stdout = stdout.lock().into_raw_mode().unwrap();
-    let mut stdin = async_stdin().bytes();
+/// What a key does in the memory view, if anything. Hex digits are only taken while paused,
+/// otherwise they're left for the keypad.
+fn memory_input(key: char, paused: bool) -> Option<MemoryInput> {
+    match key {
+        'k' => Some(MemoryInput::Up),
+        'j' => Some(MemoryInput::Down),
+        'h' => Some(MemoryInput::Left),
+        'l' => Some(MemoryInput::Right),
+        '<' => Some(MemoryInput::PageUp),
+        '>' => Some(MemoryInput::PageDown),
+        '\t' => Some(MemoryInput::SwitchFocus),
+        'i' => Some(MemoryInput::GoToI),
+        'p' => Some(MemoryInput::GoToPc),
+        ' ' => Some(MemoryInput::Pause),
+        '\r' | '\n' => Some(MemoryInput::Enter),
+        '\x7f' | '\x08' => Some(MemoryInput::Cancel),
+        c if paused && c.is_ascii_hexdigit() => c.to_digit(16).map(|digit| MemoryInput::Digit(digit as u8)),
+        c => memory::search_input(c)
+    }
+}
 
-    print!("{}", termion::cursor::Hide);
+/// `bitmap` in half blocks, two rows of pixels a line
+fn bitmap_lines(bitmap: &Bitmap) -> Vec<String> {
+    (0..bitmap.height).step_by(2).map(|y| {
+        (0..bitmap.width).map(|x| match (bitmap.get(x, y), bitmap.get(x, y + 1)) {
+            (false, false) => ' ',
+            (true, false) => '▀',
+            (false, true) => '▄',
+            (true, true) => '█'
+        }).collect()
+    }).collect()
+}
 
-    if example_program {
-        print!("{}", termion::clear::All);
-        println!("No file specified, running example program");
-        thread::sleep(Duration::from_secs(2));
+fn print_sprites(view: &SpriteView, cpu: &Cpu) {
+    let width = termion::terminal_size().map(|(w, _)| w as usize).unwrap_or(64);
+    let strip = view.strip(cpu, view.strip_count(width));
+
+    let mut lines = vec![view.heading(), String::new()];
+    lines.extend(bitmap_lines(&strip));
+    lines.push(String::new());
+    for line in view.lines() {
+        lines.extend(wrap(&line, width));
+    }
+
+    let mut out = String::new();
+    for (y, line) in lines.iter().enumerate() {
+        out += &format!("{}{}{}", termion::cursor::Goto(1, y as u16 + 1), line, termion::clear::UntilNewline);
     }
+    print!("{}{}", out, termion::clear::AfterCursor);
+}
+
+fn print_help(help: &[String]) {
+    let width = termion::terminal_size().map(|(w, _)| w as usize).unwrap_or(64).max(20) - 2;
 
-    let program_name = match filepath {
-        Some(path) => Path::new(path).file_stem().unwrap().to_str().unwrap(),
-        None => "Example"
-    };
+    let mut y = 1;
+    for paragraph in help {
+        let lines = if paragraph.is_empty() { vec![String::new()] } else { wrap(paragraph, width) };
+        for line in lines {
+            print!("{}{}", termion::cursor::Goto(2, y), line);
+            y += 1;
+        }
+    }
+
+    print!("{}press ? to close", termion::cursor::Goto(2, y + 1));
+}
+
+/// What's on the terminal, so we know when it needs clearing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum View {
+    Screen,
+    Debug,
+    Memory,
+    Sprites,
+    Help
+}
 
-    loop {
-        print!("{}", termion::clear::All);
+/// Draws the display with block or braille characters in the terminal.
+///
+/// Most terminals can't tell us when a key is released, so unless the terminal supports
+/// the kitty keyboard protocol a key counts as held for as long as the terminal repeats it.
+pub struct TermionFrontend {
+    stdout: RawTerminal<io::Stdout>,
+    stdin: io::Bytes<AsyncReader>,
+    program_name: String,
+    help: Vec<String>,
+    show_help: bool,
+    debug_mode: bool,
+    memory: MemoryView,
+    show_memory: bool,
+    sprites: SpriteView,
+    show_sprites: bool,
+    renderer: CellRenderer,
+    // None until the first frame is drawn
+    view: Option<View>,
+    // Everything is drawn again when the terminal is resized
+    size: Option<(u16, u16)>,
+    keymap: Keymap,
+    kitty: bool,
+    // Bytes from the terminal that are the start of an escape sequence
+    pending: Vec<u8>,
+    hold: KeyHold,
+    beeping: bool,
+    quit: bool
+}
+
+impl TermionFrontend {
+    pub fn new(rom: &Rom, keymap: Keymap, options: &TermionOptions) -> Result<TermionFrontend, String> {
+        let (columns, rows) = options.cells.size(&Display::new());
+        let (columns, rows) = (columns as u16, if options.debug { DEBUG_ROWS } else { rows as u16 });
+        let term_size = termion::terminal_size().map_err(|e| e.to_string())?;
+        if term_size.0 < columns || term_size.1 < rows {
+            return Err(format!("window size needs to be at least {}x{}", columns, rows));
+        }
+
+        let mut stdout = io::stdout().into_raw_mode().map_err(|e| e.to_string())?;
+        let mut stdin = async_stdin().bytes();
+        print!("{}", termion::cursor::Hide);
+
+        let kitty = match options.keys {
+            KeyMode::Auto => detect_kitty(&mut stdin, &mut stdout),
+            KeyMode::Kitty => true,
+            KeyMode::Hold => false
+        };
+        if kitty {
+            print!("{}", keys::KITTY_ENABLE);
+        }
+
+        Ok(TermionFrontend {
+            stdout, stdin, program_name: rom.title.clone(),
+            help: help_lines(rom), show_help: false, debug_mode: options.debug,
+            memory: MemoryView::new(), show_memory: false, sprites: SpriteView::new(sprites::sheet_path(rom)),
+            show_sprites: false,
+            renderer: CellRenderer::new(options.cells), view: None, size: None,
+            keymap, kitty, pending: Vec::new(), hold: KeyHold::new(options.hold_delay), beeping: false, quit: false
+        })
+    }
+}
 
-        if example_program || debug_mode {
-            print_debug_info(&cpu, program_name);
+impl Frontend for TermionFrontend {
+    fn render(&mut self, cpu: &Cpu) {
+        self.memory.update(cpu);
+        self.sprites.update(cpu);
+        let view = if self.show_help {
+            View::Help
+        } else if self.show_memory {
+            View::Memory
+        } else if self.show_sprites {
+            View::Sprites
+        } else if self.debug_mode {
+            View::Debug
         } else {
-            draw_screen(&cpu.display);
+            View::Screen
+        };
+        let size = termion::terminal_size().ok();
+        let changed = self.view != Some(view) || self.size != size;
+        if changed {
+            print!("{}", termion::clear::All);
+            self.renderer.invalidate();
+            self.view = Some(view);
+            self.size = size;
+        }
+
+        match view {
+            View::Help => if changed {
+                print_help(&self.help);
+            },
+            View::Debug => print_debug_info(cpu, &self.program_name),
+            View::Memory => print_memory(&self.memory, cpu),
+            View::Sprites => print_sprites(&self.sprites, cpu),
+            View::Screen => draw_screen(&cpu.display, &mut self.renderer)
+        }
+
+        self.stdout.flush().unwrap();
+    }
+
+    fn poll_input(&mut self) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        let now = Instant::now();
+
+        while let Some(Ok(b)) = self.stdin.next() {
+            self.pending.push(b);
+        }
+        let events = if self.kitty {
+            let (events, used) = keys::parse_kitty(&self.pending);
+            self.pending.drain(..used);
+            events
+        } else {
+            self.pending.drain(..).map(|b| keys::KeyEvent { key: b as char, kind: KeyEventKind::Press }).collect()
+        };
+
+        for event in events {
+            if self.show_memory && event.kind == KeyEventKind::Press {
+                if let Some(input) = memory_input(event.key, self.memory.paused()) {
+                    inputs.extend(self.memory.handle(input));
+                    continue;
+                }
+            }
+            if self.show_sprites && event.kind == KeyEventKind::Press {
+                if let Some(input) = sprites::sprite_input(event.key) {
+                    self.sprites.handle(input);
+                    continue;
+                }
+            }
+            match (event.key, event.kind) {
+                // ; to quit
+                (';', KeyEventKind::Press) => self.quit = true,
+                ('?', KeyEventKind::Press) => self.show_help = !self.show_help,
+                ('/', KeyEventKind::Press) => {
+                    self.show_memory = !self.show_memory;
+                    self.show_sprites = false;
+                },
+                ('\\', KeyEventKind::Press) => {
+                    self.show_sprites = !self.show_sprites;
+                    self.show_memory = false;
+                },
+                ('[', KeyEventKind::Press) => inputs.push(Input::Reset),
+                (']', KeyEventKind::Press) => inputs.push(Input::Reload),
+                (c, kind) => if let Some(key) = self.keymap.keypad_key(c) {
+                    match kind {
+                        KeyEventKind::Press if self.kitty => inputs.push(Input::KeyDown(key)),
+                        KeyEventKind::Press => if self.hold.press(key, now) {
+                            inputs.push(Input::KeyDown(key));
+                        },
+                        KeyEventKind::Repeat => {},
+                        KeyEventKind::Release => inputs.push(Input::KeyUp(key))
+                    }
+                }
+            }
+        }
+
+        if !self.kitty {
+            inputs.extend(self.hold.released(now).into_iter().map(Input::KeyUp));
+        }
+        inputs
+    }
+
+    fn play_audio(&mut self, beeping: bool) {
+        // Ring the terminal bell once at the start of each beep
+        if beeping && !self.beeping {
+            print!("\x07");
+        }
+        self.beeping = beeping;
+    }
+
+    fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    fn paused(&self) -> bool {
+        self.show_memory && self.memory.paused()
+    }
+}
+
+impl Drop for TermionFrontend {
+    fn drop(&mut self) {
+        if self.kitty {
+            print!("{}", keys::KITTY_DISABLE);
         }
+        print!("{}{}", termion::clear::All, termion::cursor::Show);
+        let _ = self.stdout.flush();
+    }
+}
 
-        cpu.tick();
 
-        if !cpu.running {
-            // wait for keypress
-            cpu.press_key(0x2);
+fn print_launcher(launcher: &Launcher) {
+    let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+    let (lines, highlight) = launcher.screen_lines(width as usize, height as usize);
+
+    print!("{}", termion::clear::All);
+    for (y, line) in lines.iter().enumerate() {
+        print!("{}", termion::cursor::Goto(1, (y + 1) as u16));
+        if highlight == Some(y) {
+            print!("{}{:<w$}{}", termion::style::Invert, line, termion::style::Reset, w = width as usize);
+        } else {
+            print!("{}", line);
+        }
+    }
+}
+
+/// Let the user pick a ROM from `launcher` in the terminal. None if they quit instead.
+pub fn choose_rom(launcher: &mut Launcher) -> Result<Option<PathBuf>, String> {
+    let mut stdout = io::stdout().into_raw_mode().map_err(|e| e.to_string())?;
+    let mut keys = async_stdin().keys();
+    print!("{}", termion::cursor::Hide);
+
+    let mut state = LauncherState::Browsing;
+    let mut redraw = true;
+    while state == LauncherState::Browsing {
+        if redraw {
+            print_launcher(launcher);
+            stdout.flush().map_err(|e| e.to_string())?;
         }
 
-        let b = stdin.next();
-        match b {
-            // ; to quit
-            Some(Ok(b';')) => break,
-
-            // key map
-            Some(Ok(b'1')) => cpu.press_key(0x1),
-            Some(Ok(b'2')) => cpu.press_key(0x2),
-            Some(Ok(b'3')) => cpu.press_key(0x3),
-            Some(Ok(b'4')) => cpu.press_key(0xC),
-            Some(Ok(b'q')) => cpu.press_key(0x4),
-            Some(Ok(b'w')) => cpu.press_key(0x5),
-            Some(Ok(b'e')) => cpu.press_key(0x6),
-            Some(Ok(b'r')) => cpu.press_key(0xD),
-            Some(Ok(b'a')) => cpu.press_key(0x7),
-            Some(Ok(b's')) => cpu.press_key(0x8),
-            Some(Ok(b'd')) => cpu.press_key(0x9),
-            Some(Ok(b'f')) => cpu.press_key(0xE),
-            Some(Ok(b'z')) => cpu.press_key(0xA),
-            Some(Ok(b'x')) => cpu.press_key(0x0),
-            Some(Ok(b'c')) => cpu.press_key(0xB),
-            Some(Ok(b'v')) => cpu.press_key(0xF),
-            _ => true
+        let input = match keys.next() {
+            Some(Ok(key)) => match key {
+                Key::Up => Some(LauncherInput::Up),
+                Key::Down => Some(LauncherInput::Down),
+                Key::PageUp => Some(LauncherInput::PageUp),
+                Key::PageDown => Some(LauncherInput::PageDown),
+                Key::Backspace => Some(LauncherInput::Backspace),
+                Key::Char('\n') | Key::Char('\r') => Some(LauncherInput::Select),
+                Key::Esc | Key::Ctrl('c') => Some(LauncherInput::Cancel),
+                Key::Char(c) => Some(LauncherInput::Char(c)),
+                _ => None
+            },
+            _ => {
+                thread::sleep(Duration::from_millis(10));
+                None
+            }
         };
 
-        stdout.flush().unwrap();
-        thread::sleep(Duration::from_millis(1));
+        redraw = input.is_some();
+        if let Some(input) = input {
+            state = launcher.handle(input);
+        }
     }
 
-    print!("{}", termion::cursor::Show);
+    print!("{}{}{}", termion::clear::All, termion::cursor::Goto(1, 1), termion::cursor::Show);
+    stdout.flush().map_err(|e| e.to_string())?;
+
+    match state {
+        LauncherState::Chosen(path) => Ok(Some(path)),
+        _ => Ok(None)
+    }
 }
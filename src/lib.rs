@@ -1,12 +1,52 @@
+// wasm-bindgen needs std for its allocator and panic handler, even without files and threads
+#![cfg_attr(not(any(feature = "std", feature = "wasm")), no_std)]
+
+#[macro_use]
+extern crate alloc;
+#[cfg(any(feature = "std", feature = "wasm"))]
+extern crate core;
 extern crate rand;
+#[cfg(feature = "termion")]
 extern crate termion;
+#[cfg(feature = "sdl")]
 extern crate sdl2;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "wasm")]
+extern crate js_sys;
+#[cfg(feature = "wasm")]
+extern crate web_sys;
 
 pub mod utils;
 pub use utils::get_nth_hex_digit;
 
+pub mod chip8;
+pub use chip8::Chip8;
+
 pub mod cpu;
 pub use cpu::Cpu;
+pub mod timing;
+pub mod font;
+
+pub mod rom;
+pub use rom::Rom;
+pub mod docs;
+#[cfg(feature = "std")]
+pub mod library;
+
+pub mod disasm;
+pub mod asm;
+pub mod analysis;
+pub mod profiler;
+pub mod cheats;
+pub mod sprites;
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod batch;
 
+#[cfg(feature = "std")]
 pub mod interface;
-pub use interface::parse_args_and_run;
\ No newline at end of file
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub use interface::parse_args_and_run;
@@ -0,0 +1,171 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rom::Rom;
+
+/// Most ROMs kept in the recently played list
+const MAX_RECENT: usize = 10;
+
+/// What kind of ROM it is, taken from the name of the directory it's in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    Game,
+    Demo,
+    Program,
+    Other
+}
+
+impl Category {
+    fn from_dir_name(name: &str) -> Category {
+        match name.to_lowercase().as_str() {
+            "games" | "game" => Category::Game,
+            "demos" | "demo" => Category::Demo,
+            "programs" | "program" => Category::Program,
+            _ => Category::Other
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Category::Game => "Games",
+            Category::Demo => "Demos",
+            Category::Program => "Programs",
+            Category::Other => "Other"
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryEntry {
+    pub rom: Rom,
+    pub category: Category
+}
+
+impl LibraryEntry {
+    /// True if the title, author or genre contains `query`, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let genre = self.rom.docs.as_ref().and_then(|docs| docs.field("genre"));
+        self.rom.title.to_lowercase().contains(&query)
+            || self.rom.author.as_ref().is_some_and(|author| author.to_lowercase().contains(&query))
+            || genre.is_some_and(|genre| genre.to_lowercase().contains(&query))
+    }
+}
+
+/// All the ROMs in a directory tree, e.g. the bundled examples
+#[derive(Debug, Clone, PartialEq)]
+pub struct Library {
+    /// Sorted by category, then title
+    pub entries: Vec<LibraryEntry>
+}
+
+impl Library {
+    /// Find every `.ch8` file under `dir`. Files that aren't valid ROMs are left out.
+    pub fn scan<P: AsRef<Path>>(dir: P) -> io::Result<Library> {
+        let mut entries = Vec::new();
+        scan_dir(dir.as_ref(), Category::Other, &mut entries)?;
+        entries.sort_by_key(|entry| (entry.category, entry.rom.title.to_lowercase()));
+        Ok(Library { entries })
+    }
+
+    /// Index of the ROM loaded from `path`
+    pub fn position(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.rom.path.as_ref().is_some_and(|p| p == path))
+    }
+}
+
+fn scan_dir(dir: &Path, category: Category, entries: &mut Vec<LibraryEntry>) -> io::Result<()> {
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+
+        if path.is_dir() {
+            let name = path.file_name().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            let sub_category = match Category::from_dir_name(&name) {
+                Category::Other => category,
+                c => c
+            };
+            scan_dir(&path, sub_category, entries)?;
+        } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("ch8")) {
+            if let Ok(rom) = Rom::from_path(&path) {
+                entries.push(LibraryEntry { rom, category });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ROMs played most recently, newest first, stored one path per line
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RecentList {
+    pub paths: Vec<PathBuf>
+}
+
+impl RecentList {
+    pub fn new() -> RecentList {
+        RecentList { paths: Vec::new() }
+    }
+
+    /// Where the list is kept between runs, `~/.rust-chip8-recent`
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("HOME").map(|home| Path::new(&home).join(".rust-chip8-recent"))
+    }
+
+    /// Read the list from `path`, an empty list if it doesn't exist yet
+    pub fn load(path: &Path) -> RecentList {
+        let text = fs::read_to_string(path).unwrap_or_default();
+        let paths = text.lines().filter(|line| !line.is_empty()).map(PathBuf::from).take(MAX_RECENT).collect();
+        RecentList { paths }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let lines: Vec<String> = self.paths.iter().map(|p| p.to_string_lossy().into_owned()).collect();
+        fs::write(path, lines.join("\n") + "\n")
+    }
+
+    /// Move `path` to the top of the list
+    pub fn add(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+        self.paths.insert(0, path.to_path_buf());
+        self.paths.truncate(MAX_RECENT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_examples() {
+        let library = Library::scan("examples").unwrap();
+        let pong = library.position(Path::new("examples/games/Pong [Paul Vervalin, 1990].ch8")).unwrap();
+        assert_eq!(library.entries[pong].category, Category::Game);
+        assert!(library.entries[pong].matches("vervalin"));
+        assert!(!library.entries[pong].matches("maze"));
+
+        let categories: Vec<Category> = library.entries.iter().map(|e| e.category).collect();
+        let mut sorted = categories.clone();
+        sorted.sort();
+        assert_eq!(categories, sorted);
+        assert!(categories.contains(&Category::Demo) && categories.contains(&Category::Program));
+    }
+
+    #[test]
+    fn recent_list() {
+        let mut recent = RecentList::new();
+        for i in 0..MAX_RECENT + 2 {
+            recent.add(Path::new(&format!("{}.ch8", i)));
+        }
+        recent.add(Path::new("5.ch8"));
+        assert_eq!(recent.paths.len(), MAX_RECENT);
+        assert_eq!(recent.paths[0], Path::new("5.ch8"));
+        assert_eq!(recent.paths[1], Path::new(&format!("{}.ch8", MAX_RECENT + 1)));
+
+        let file = env::temp_dir().join(format!("rust-chip8-recent-test-{}", ::std::process::id()));
+        recent.save(&file).unwrap();
+        assert_eq!(RecentList::load(&file), recent);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(RecentList::load(&file), RecentList::new());
+    }
+}
@@ -1,7 +1,8 @@
 extern crate rust_chip8;
 use std::env;
+use std::process;
 
 fn main() {
     let args : Vec<_> = env::args().collect();
-    rust_chip8::parse_args_and_run(args);
+    process::exit(rust_chip8::parse_args_and_run(args));
 }
@@ -0,0 +1,177 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use cpu::FRAME_HZ;
+use disasm::disassemble;
+
+/// Opcode classes by the top nibble of the instruction
+const CLASSES: [&str; 16] = [
+    "0nnn CLS/RET/SYS", "1nnn JP", "2nnn CALL", "3xkk SE", "4xkk SNE", "5xy0 SE", "6xkk LD", "7xkk ADD",
+    "8xyn arithmetic", "9xy0 SNE", "Annn LD I", "Bnnn JP V0", "Cxkk RND", "Dxyn DRW", "Exnn SKP/SKNP",
+    "Fxnn LD/ADD"
+];
+
+/// Addresses listed in the report
+const HOT_SPOTS: usize = 32;
+
+/// Where the time goes in a running program, see `Cpu::start_profiling`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// Times each address was executed
+    pub counts: Vec<u64>,
+    /// Instructions executed in each class, by top nibble
+    pub classes: [u64; 16],
+    pub frames: u64,
+    /// Frames spent stopped at Fx0A waiting for a key
+    pub key_wait_frames: u64,
+    /// Number of frames with each number of DRW instructions in them
+    pub draws_per_frame: BTreeMap<u32, u64>,
+    /// Instructions executed with each call stack, which starts with the program start address
+    /// followed by the address of each subroutine called
+    pub stacks: BTreeMap<Vec<u16>, u64>,
+    draws: u32,
+    call_stack: Vec<u16>
+}
+
+impl Profile {
+    /// A profile of a program starting at `program_start` in `memory_size` bytes of memory
+    pub fn new(memory_size: usize, program_start: u16) -> Profile {
+        Profile {
+            counts: vec![0; memory_size], classes: [0; 16], frames: 0, key_wait_frames: 0,
+            draws_per_frame: BTreeMap::new(), stacks: BTreeMap::new(), draws: 0, call_stack: vec![program_start]
+        }
+    }
+
+    /// Count `instruction` at `addr`, just before it's executed
+    pub fn instruction(&mut self, addr: u16, instruction: u16) {
+        if let Some(count) = self.counts.get_mut(addr as usize) {
+            *count += 1;
+        }
+        self.classes[(instruction >> 12) as usize] += 1;
+        match self.stacks.get_mut(&self.call_stack) {
+            Some(count) => *count += 1,
+            None => { self.stacks.insert(self.call_stack.clone(), 1); }
+        }
+
+        match instruction {
+            a if a & 0xF000 == 0x2000 => self.call_stack.push(a & 0xFFF),
+            // The bottom of the stack is the program itself, which can't return
+            0x00EE if self.call_stack.len() > 1 => { self.call_stack.pop(); },
+            a if a & 0xF000 == 0xD000 => self.draws += 1,
+            _ => {}
+        }
+    }
+
+    /// The program has been started again from the top, so it's not in any subroutine
+    pub fn restart(&mut self) {
+        self.call_stack.truncate(1);
+    }
+
+    /// Called at the end of every frame, `waiting_for_key` if the program is stopped at Fx0A
+    pub fn end_frame(&mut self, waiting_for_key: bool) {
+        self.frames += 1;
+        if waiting_for_key {
+            self.key_wait_frames += 1;
+        }
+        *self.draws_per_frame.entry(self.draws).or_insert(0) += 1;
+        self.draws = 0;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.classes.iter().sum()
+    }
+
+    /// A readable summary, with the hottest addresses disassembled from `memory`
+    pub fn report(&self, memory: &[u8]) -> String {
+        let total = self.total();
+        let percent = |n: u64| if total == 0 { 0.0 } else { 100.0 * n as f64 / total as f64 };
+        let seconds = |frames: u64| frames as f64 / FRAME_HZ as f64;
+
+        let mut report = format!("{} instructions in {} frames ({:.1}s)\n\n", total, self.frames, seconds(self.frames));
+
+        let waiting = if self.frames == 0 { 0.0 } else { 100.0 * self.key_wait_frames as f64 / self.frames as f64 };
+        report += &format!("Waiting for a key (Fx0A): {} frames ({:.1}s, {:.0}%)\n",
+                           self.key_wait_frames, seconds(self.key_wait_frames), waiting);
+        let draws: u64 = self.draws_per_frame.iter().map(|(&draws, &frames)| draws as u64 * frames).sum();
+        report += &format!("Draws: {} ({:.2} a frame)\n", draws,
+                           if self.frames == 0 { 0.0 } else { draws as f64 / self.frames as f64 });
+        for (draws, frames) in &self.draws_per_frame {
+            report += &format!("  {:>3} draws: {} frames\n", draws, frames);
+        }
+
+        report += "\nBy opcode:\n";
+        let mut classes: Vec<(usize, u64)> = self.classes.iter().cloned().enumerate().filter(|&(_, n)| n > 0).collect();
+        classes.sort_by_key(|&(_, count)| core::cmp::Reverse(count));
+        for (class, count) in classes {
+            report += &format!("  {:<18} {:>10} {:>6.2}%\n", CLASSES[class], count, percent(count));
+        }
+
+        report += "\nHottest addresses:\n";
+        let mut addresses: Vec<(usize, u64)> = self.counts.iter().cloned().enumerate().filter(|&(_, n)| n > 0).collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for &(addr, count) in addresses.iter().take(HOT_SPOTS) {
+            let text = match memory.get(addr..addr + 2) {
+                Some(bytes) => disassemble((bytes[0] as u16) << 8 | bytes[1] as u16),
+                None => String::new()
+            };
+            report += &format!("  0x{:03X}  {:<18} {:>10} {:>6.2}%\n", addr, text, count, percent(count));
+        }
+        report
+    }
+
+    /// The call stacks in the "folded" format read by flamegraph.pl, inferno and speedscope:
+    /// one line per stack, with subroutines named after their address
+    pub fn folded_stacks(&self) -> String {
+        let mut folded = String::new();
+        for (stack, count) in &self.stacks {
+            let names: Vec<String> = stack.iter().enumerate()
+                .map(|(depth, addr)| if depth == 0 { format!("main_{:03X}", addr) } else { format!("sub_{:03X}", addr) })
+                .collect();
+            folded += &format!("{} {}\n", names.join(";"), count);
+        }
+        folded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::Cpu;
+    use rom::Rom;
+
+    fn profiled(bytes: Vec<u8>, frames: u32) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&Rom::from_bytes(bytes).unwrap()).unwrap();
+        cpu.start_profiling();
+        for _ in 0..frames {
+            cpu.run_frame(540);
+        }
+        cpu
+    }
+
+    #[test]
+    fn counts_and_stacks() {
+        // CALL 0x206 / JP 0x200 / (0x204 unused) / DRW V0, V0, 1 / RET
+        let cpu = profiled(vec![0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0xD0, 0x01, 0x00, 0xEE], 1);
+        let profile = cpu.profile().unwrap();
+        // 9 instructions a frame at 540
+        assert_eq!(profile.total(), 9);
+        assert_eq!((profile.counts[0x200], profile.counts[0x202], profile.counts[0x206]), (3, 2, 2));
+        assert_eq!((profile.classes[0x2], profile.classes[0xD], profile.classes[0x0]), (3, 2, 2));
+        assert_eq!(profile.draws_per_frame.get(&2), Some(&1));
+        assert_eq!(profile.folded_stacks(), "main_200 5\nmain_200;sub_206 4\n");
+
+        let report = profile.report(&cpu.memory);
+        assert!(report.starts_with("9 instructions in 1 frames"));
+        assert!(report.contains("0x200  CALL 0x206"));
+    }
+
+    #[test]
+    fn key_waits() {
+        // LD V0, K
+        let profile = profiled(vec![0xF0, 0x0A], 30).profile().unwrap().clone();
+        assert_eq!((profile.frames, profile.key_wait_frames, profile.total()), (30, 30, 1));
+        assert!(profile.report(&[]).contains("Waiting for a key (Fx0A): 30 frames (0.5s, 100%)"));
+    }
+}
@@ -0,0 +1,218 @@
+use core::fmt;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use cpu::MAX_PROGRAM_SIZE;
+use docs::{RomDocs, System, uses_super_chip_instructions};
+use utils::{sha1, to_hex};
+
+#[derive(Debug)]
+pub enum RomError {
+    /// The file couldn't be read
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// The ROM has no bytes in it
+    Empty,
+    /// The ROM doesn't fit in the memory available for programs
+    TooLarge { size: usize, max: usize }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "std")]
+            RomError::Io(ref e) => write!(f, "{}", e),
+            RomError::Empty => write!(f, "ROM is empty"),
+            RomError::TooLarge { size, max } => write!(f, "ROM is {} bytes but only {} fit in memory", size, max)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> RomError {
+        RomError::Io(e)
+    }
+}
+
+/// A program to be loaded into memory, along with what we know about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    /// Where the ROM was loaded from, if it came from a file
+    #[cfg(feature = "std")]
+    pub path: Option<PathBuf>,
+    /// Taken from the file name, e.g. "Pong" for "Pong [Paul Vervalin, 1990].ch8"
+    pub title: String,
+    pub author: Option<String>,
+    pub year: Option<String>,
+    /// SHA-1 of `bytes` as lowercase hex
+    pub hash: String,
+    /// From the `.txt` file next to the ROM
+    pub docs: Option<RomDocs>
+}
+
+impl Rom {
+    /// A ROM with no file name, titled "Untitled"
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Rom, RomError> {
+        if bytes.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if bytes.len() > MAX_PROGRAM_SIZE {
+            return Err(RomError::TooLarge { size: bytes.len(), max: MAX_PROGRAM_SIZE });
+        }
+
+        let hash = to_hex(&sha1(&bytes));
+        Ok(Rom {
+            bytes, #[cfg(feature = "std")] path: None, title: "Untitled".to_string(), author: None, year: None, hash,
+            docs: None
+        })
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Rom, RomError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Rom::from_bytes(bytes)
+    }
+
+    /// Load a ROM from a file, taking the title, author and year from file names
+    /// like "Title [Author, Year].ch8", and reading the documentation in "Title [Author, Year].txt"
+    #[cfg(feature = "std")]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Rom, RomError> {
+        let path = path.as_ref();
+        let mut rom = Rom::from_bytes(fs::read(path)?)?;
+
+        let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let (title, author, year) = parse_file_name(&stem);
+        rom.title = title;
+        rom.author = author;
+        rom.year = year;
+        rom.path = Some(path.to_path_buf());
+
+        rom.docs = RomDocs::find(path);
+        if rom.author.is_none() {
+            rom.author = rom.docs.as_ref().and_then(|docs| docs.author.clone());
+        }
+        Ok(rom)
+    }
+
+    /// Which system the ROM was written for, going by its documentation and the instructions in it
+    pub fn system(&self) -> System {
+        let documented = self.docs.as_ref().map(|docs| docs.systems.clone()).unwrap_or_default();
+        let super_chip_documented = documented.is_empty() || documented.contains(&System::SuperChip8);
+
+        if (uses_super_chip_instructions(&self.bytes) && super_chip_documented) || documented == [System::SuperChip8] {
+            System::SuperChip8
+        } else {
+            System::Chip8
+        }
+    }
+}
+
+fn is_year(s: &str) -> bool {
+    // Some years are only partly known, e.g. "199x"
+    s.len() == 4 && s.chars().all(|c| c.is_ascii_digit() || c == 'x')
+}
+
+/// Split a file name (without extension) like "Title [Author, Year] (alt)" into
+/// ("Title (alt)", Some("Author"), Some("Year"))
+pub fn parse_file_name(stem: &str) -> (String, Option<String>, Option<String>) {
+    let (open, close) = match (stem.find('['), stem.find(']')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => return (stem.trim().to_string(), None, None)
+    };
+
+    let mut title = stem[..open].trim().to_string();
+    let rest = stem[close + 1..].trim();
+    if !rest.is_empty() {
+        title.push(' ');
+        title.push_str(rest);
+    }
+
+    let credits = stem[open + 1..close].trim();
+    let (author, year) = match credits.rfind(',') {
+        Some(comma) if is_year(credits[comma + 1..].trim()) => {
+            (credits[..comma].trim(), Some(credits[comma + 1..].trim().to_string()))
+        },
+        _ if is_year(credits) => ("", Some(credits.to_string())),
+        _ => (credits, None)
+    };
+    let author = if author.is_empty() { None } else { Some(author.to_string()) };
+
+    (title, author, year)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(stem: &str) -> (String, Option<String>, Option<String>) {
+        parse_file_name(stem)
+    }
+
+    fn some(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(parsed("Pong [Paul Vervalin, 1990]"), ("Pong".to_string(), some("Paul Vervalin"), some("1990")));
+        assert_eq!(parsed("Maze [David Winter, 199x]"), ("Maze".to_string(), some("David Winter"), some("199x")));
+        assert_eq!(parsed("15 Puzzle [Roger Ivie] (alt)"), ("15 Puzzle (alt)".to_string(), some("Roger Ivie"), None));
+        assert_eq!(parsed("Trip8 Demo (2008) [Revival Studios]"),
+                   ("Trip8 Demo (2008)".to_string(), some("Revival Studios"), None));
+        assert_eq!(parsed("Brick (Brix hack, 1990)"), ("Brick (Brix hack, 1990)".to_string(), None, None));
+        assert_eq!(parsed("Airplane"), ("Airplane".to_string(), None, None));
+    }
+
+    #[test]
+    fn validation() {
+        match Rom::from_bytes(vec![]) {
+            Err(RomError::Empty) => {},
+            other => panic!("{:?}", other)
+        }
+        match Rom::from_bytes(vec![0; MAX_PROGRAM_SIZE + 1]) {
+            Err(RomError::TooLarge { size, max }) => assert_eq!((size, max), (MAX_PROGRAM_SIZE + 1, MAX_PROGRAM_SIZE)),
+            other => panic!("{:?}", other)
+        }
+        assert!(Rom::from_bytes(vec![0; MAX_PROGRAM_SIZE]).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn from_reader() {
+        let rom = Rom::from_reader(&b"abc"[..]).unwrap();
+        assert_eq!(rom.bytes, b"abc");
+        assert_eq!(rom.hash, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert!(rom.docs.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn from_path() {
+        let rom = Rom::from_path("examples/games/Pong [Paul Vervalin, 1990].ch8").unwrap();
+        assert_eq!(rom.title, "Pong");
+        assert_eq!(rom.author, some("Paul Vervalin"));
+        assert_eq!(rom.year, some("1990"));
+        assert_eq!(rom.bytes.len(), 246);
+        assert_eq!(rom.system(), System::Chip8);
+        assert!(rom.docs.is_some());
+
+        let rom = Rom::from_path("examples/games/Blitz [David Winter].ch8").unwrap();
+        assert_eq!(rom.author, some("David Winter"));
+
+        match Rom::from_path("examples/games/does not exist.ch8") {
+            Err(RomError::Io(_)) => {},
+            other => panic!("{:?}", other)
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use alloc::vec::Vec;
+
+/// The shape of a sprite in memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpriteSize {
+    /// 8 pixels wide and this many rows, a byte a row, as drawn by Dxyn
+    Small(u8),
+    /// 16x16, two bytes a row, as drawn by Dxy0 on SUPER-CHIP
+    Large
+}
+
+impl SpriteSize {
+    /// The size drawn by a DRW instruction with `n` as its last nibble
+    pub fn from_nibble(n: u8) -> SpriteSize {
+        if n == 0 { SpriteSize::Large } else { SpriteSize::Small(n) }
+    }
+
+    pub fn width(&self) -> usize {
+        match *self {
+            SpriteSize::Small(_) => 8,
+            SpriteSize::Large => 16
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match *self {
+            SpriteSize::Small(rows) => rows as usize,
+            SpriteSize::Large => 16
+        }
+    }
+
+    /// Bytes of memory the sprite takes up
+    pub fn bytes(&self) -> usize {
+        self.width() / 8 * self.height()
+    }
+}
+
+/// A black and white image, for drawing sprites into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    /// Row by row from the top left, true for a set pixel
+    pub pixels: Vec<bool>
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize) -> Bitmap {
+        Bitmap { width, height, pixels: vec![false; width * height] }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.pixels[y * self.width + x]
+    }
+
+    /// Draw the sprite at `addr` with its top left corner at (`x`, `y`). Anything past the end of
+    /// memory or the bitmap is left out.
+    pub fn draw_sprite(&mut self, memory: &[u8], addr: u16, size: SpriteSize, x: usize, y: usize) {
+        let bytes_per_row = size.width() / 8;
+        for row in 0..size.height() {
+            for column in 0..size.width() {
+                let byte = memory.get(addr as usize + row * bytes_per_row + column / 8).cloned().unwrap_or(0);
+                let (px, py) = (x + column, y + row);
+                if byte & (0x80 >> (column % 8)) != 0 && px < self.width && py < self.height {
+                    self.pixels[py * self.width + px] = true;
+                }
+            }
+        }
+    }
+
+    /// The image as a binary PBM file, which most image viewers and editors can open
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut pbm = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+        for row in self.pixels.chunks(self.width.max(1)) {
+            for byte in row.chunks(8) {
+                pbm.push(byte.iter().enumerate().fold(0, |b, (i, &set)| b | (set as u8) << (7 - i)));
+            }
+        }
+        pbm
+    }
+}
+
+/// Draw `sprites` in a grid `columns` wide with a pixel between them, each in a cell big enough for
+/// the largest one
+///
+/// ```
+/// use rust_chip8::sprites::{sheet, SpriteSize};
+///
+/// let memory = [0xF0, 0x90, 0xF0];
+/// let sheet = sheet(&memory, &[(0, SpriteSize::Small(3)), (1, SpriteSize::Small(2))], 8);
+/// assert_eq!((sheet.width, sheet.height), (17, 3));
+/// assert!(sheet.get(9, 0) && !sheet.get(10, 0) && sheet.get(9, 1));
+/// ```
+pub fn sheet(memory: &[u8], sprites: &[(u16, SpriteSize)], columns: usize) -> Bitmap {
+    let columns = columns.max(1).min(sprites.len().max(1));
+    let rows = sprites.len().div_ceil(columns);
+    let cell_width = sprites.iter().map(|&(_, size)| size.width()).max().unwrap_or(0);
+    let cell_height = sprites.iter().map(|&(_, size)| size.height()).max().unwrap_or(0);
+
+    let mut bitmap = Bitmap::new((columns * (cell_width + 1)).saturating_sub(1),
+                                 (rows * (cell_height + 1)).saturating_sub(1));
+    for (i, &(addr, size)) in sprites.iter().enumerate() {
+        bitmap.draw_sprite(memory, addr, size, i % columns * (cell_width + 1), i / columns * (cell_height + 1));
+    }
+    bitmap
+}
+
+/// `count` sprites of `size` one after another in memory from `start`
+pub fn consecutive(start: u16, size: SpriteSize, count: usize) -> Vec<(u16, SpriteSize)> {
+    (0..count).map(|i| (start + (i * size.bytes()) as u16, size)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(SpriteSize::from_nibble(5), SpriteSize::Small(5));
+        assert_eq!(SpriteSize::from_nibble(0), SpriteSize::Large);
+        assert_eq!((SpriteSize::Large.width(), SpriteSize::Large.height(), SpriteSize::Large.bytes()), (16, 16, 32));
+        assert_eq!(consecutive(0x200, SpriteSize::Small(5), 3),
+                   vec![(0x200, SpriteSize::Small(5)), (0x205, SpriteSize::Small(5)), (0x20A, SpriteSize::Small(5))]);
+    }
+
+    #[test]
+    fn large_sprite() {
+        let mut memory = vec![0; 32];
+        memory[0] = 0x80;
+        memory[1] = 0x01;
+        memory[31] = 0x01;
+        let sheet = sheet(&memory, &[(0, SpriteSize::Large)], 4);
+        assert_eq!((sheet.width, sheet.height), (16, 16));
+        let set: Vec<(usize, usize)> = (0..16 * 16).map(|i| (i % 16, i / 16)).filter(|&(x, y)| sheet.get(x, y)).collect();
+        assert_eq!(set, vec![(0, 0), (15, 0), (15, 15)]);
+    }
+
+    #[test]
+    fn pbm() {
+        let mut bitmap = Bitmap::new(10, 2);
+        bitmap.draw_sprite(&[0xC1, 0xFF], 0, SpriteSize::Small(2), 2, 0);
+        let mut expected = b"P4\n10 2\n".to_vec();
+        expected.extend_from_slice(&[0b0011_0000, 0b0100_0000, 0b0011_1111, 0b1100_0000]);
+        assert_eq!(bitmap.to_pbm(), expected);
+        // Sprites off the end of memory come out blank rather than panicking
+        bitmap.draw_sprite(&[0xFF], 0, SpriteSize::Small(15), 0, 0);
+        assert_eq!(bitmap.pixels.iter().filter(|&&set| set).count(), 9 + 8);
+    }
+}
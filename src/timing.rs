@@ -0,0 +1,102 @@
+use cpu::Cpu;
+
+/// Machine cycles in a frame: the COSMAC VIP's 1.76MHz 1802 takes 8 clocks a machine cycle
+const CYCLES_PER_FRAME: u32 = 3668;
+/// Cycles each frame taken by the display, 1024 for DMA and the rest for the interrupt routine
+const DISPLAY_CYCLES: u32 = 1070;
+/// Cycles the VIP interpreter spends fetching and decoding every instruction
+const FETCH_CYCLES: u32 = 68;
+
+/// Cycles left for running instructions each frame on the COSMAC VIP
+pub const VIP_CYCLES_PER_FRAME: u32 = CYCLES_PER_FRAME - DISPLAY_CYCLES;
+
+/// How long instructions take
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    /// Every instruction takes the same time, `--speed` of them a second
+    Fixed,
+    /// Instructions take as many machine cycles as on the COSMAC VIP, and drawing waits for
+    /// the next frame
+    CosmacVip
+}
+
+impl Timing {
+    pub const NAMES: &'static [&'static str] = &["fixed", "vip"];
+
+    pub fn from_name(name: &str) -> Option<Timing> {
+        match name {
+            "fixed" => Some(Timing::Fixed),
+            "vip" => Some(Timing::CosmacVip),
+            _ => None
+        }
+    }
+}
+
+/// Approximately how many machine cycles `instruction` takes on the COSMAC VIP, given the state of
+/// `cpu` before it runs, since skips, sprites and BCD take longer for some values
+pub fn vip_cycles(cpu: &Cpu, instruction: u16) -> u32 {
+    let x = (instruction >> 8 & 0xF) as usize;
+    let y = (instruction >> 4 & 0xF) as usize;
+    let (vx, vy) = (cpu.v_reg[x], cpu.v_reg[y]);
+    let kk = instruction as u8;
+    let skip = |skipping: bool| if skipping { 18 } else { 14 };
+    let key_down = |key: u8| cpu.keys >> (key & 0xF) & 1 == 1;
+
+    let cycles = match instruction >> 12 {
+        // 256 bytes of display memory cleared one at a time
+        0x0 if instruction == 0x00E0 => 3078,
+        0x0 if instruction == 0x00EE => 10,
+        0x0 => 0,
+        0x1 => 12,
+        0x2 => 26,
+        0x3 => skip(vx == kk),
+        0x4 => skip(vx != kk),
+        0x5 => skip(vx == vy),
+        0x6 => 6,
+        0x7 => 10,
+        0x8 => 44,
+        0x9 => skip(vx != vy),
+        0xA => 12,
+        0xB => 22,
+        0xC => 36,
+        // Sprites not lined up with a byte of display memory are shifted across two bytes
+        0xD => 22 + (instruction & 0xF) as u32 * if vx % 8 == 0 { 68 } else { 136 },
+        0xE if kk == 0x9E => skip(key_down(vx)),
+        0xE => skip(!key_down(vx)),
+        _ => match kk {
+            0x07 | 0x15 | 0x18 => 10,
+            0x0A => 18,
+            0x1E => 16,
+            0x29 => 20,
+            // Each digit is found by repeated subtraction
+            0x33 => 84 + 16 * (vx / 100 + vx / 10 % 10 + vx % 10) as u32,
+            0x55 | 0x65 => 14 + 14 * (x as u32 + 1),
+            _ => 0
+        }
+    };
+    FETCH_CYCLES + cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn costs_depend_on_state() {
+        let mut cpu = Cpu::new();
+        assert_eq!(vip_cycles(&cpu, 0x6012), FETCH_CYCLES + 6);
+        assert_eq!(vip_cycles(&cpu, 0x3000), FETCH_CYCLES + 18);
+        assert_eq!(vip_cycles(&cpu, 0x3001), FETCH_CYCLES + 14);
+        assert_eq!(vip_cycles(&cpu, 0xD015), FETCH_CYCLES + 22 + 5 * 68);
+        cpu.v_reg[0] = 3;
+        assert_eq!(vip_cycles(&cpu, 0xD015), FETCH_CYCLES + 22 + 5 * 136);
+        cpu.v_reg[0] = 199;
+        assert_eq!(vip_cycles(&cpu, 0xF033), FETCH_CYCLES + 84 + 16 * 19);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Timing::from_name("vip"), Some(Timing::CosmacVip));
+        assert_eq!(Timing::from_name("fast"), None);
+    }
+}
@@ -1,4 +1,6 @@
 
+use alloc::string::String;
+
 /// Gets the nth hex digit starting from the least significant digit
 ///
 /// TODO: make this into a macro?
@@ -14,3 +16,72 @@
 pub fn get_nth_hex_digit(hex: u32, n: u32) -> u8 {
     ((hex & (0xF << 4 * n)) >> 4 * n) as u8
 }
+
+/// SHA-1 digest of `bytes`, used to identify ROMs
+///
+/// # Examples
+///
+/// ```
+/// let digest = rust_chip8::utils::sha1(b"abc");
+/// assert_eq!(rust_chip8::utils::to_hex(&digest), "a9993e364706816aba3e25717850c26c9cd0d89d");
+/// ```
+pub fn sha1(bytes: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    // Pad with a 1 bit, zeros, then the length in bits so the message is a multiple of 64 bytes
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bit_len = (bytes.len() as u64).wrapping_mul(8);
+    for i in (0..8).rev() {
+        message.push((bit_len >> (8 * i)) as u8);
+    }
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = (chunk[4 * i] as u32) << 24 | (chunk[4 * i + 1] as u32) << 16
+                | (chunk[4 * i + 2] as u32) << 8 | chunk[4 * i + 3] as u32;
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6)
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut digest = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        for j in 0..4 {
+            digest[4 * i + j] = (word >> (24 - 8 * j)) as u8;
+        }
+    }
+    digest
+}
+
+/// Lowercase hex representation of `bytes`
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,147 @@
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{AudioContext, CanvasRenderingContext2d, GainNode, ImageData, OscillatorType};
+
+use chip8::{Chip8, Settings};
+use cpu::Quirks;
+use rom::Rom;
+
+/// Keyboard keys for keypad keys 0 to F, the same layout as the qwerty keymap on the desktop
+const KEYS: &str = "x123qweasdzc4rfv";
+const FOREGROUND: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const BACKGROUND: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+const BEEP_FREQUENCY: f32 = 440.0;
+const BEEP_VOLUME: f32 = 0.1;
+
+/// A square wave that's switched on and off with the sound timer
+struct Beeper {
+    context: AudioContext,
+    gain: GainNode
+}
+
+impl Beeper {
+    fn new() -> Result<Beeper, JsValue> {
+        let context = AudioContext::new()?;
+        let oscillator = context.create_oscillator()?;
+        oscillator.set_type(OscillatorType::Square);
+        oscillator.frequency().set_value(BEEP_FREQUENCY);
+        let gain = context.create_gain()?;
+        gain.gain().set_value(0.0);
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&context.destination())?;
+        oscillator.start()?;
+        Ok(Beeper { context, gain })
+    }
+
+    fn set(&self, beeping: bool) {
+        self.gain.gain().set_value(if beeping { BEEP_VOLUME } else { 0.0 });
+    }
+}
+
+impl Drop for Beeper {
+    fn drop(&mut self) {
+        let _ = self.context.close();
+    }
+}
+
+/// The emulator as seen from JavaScript. The page calls `run_frame` and `draw` 60 times a second
+/// and passes keyboard events to `key`.
+#[wasm_bindgen]
+pub struct WebChip8 {
+    chip8: Chip8,
+    beeper: Option<Beeper>
+}
+
+#[wasm_bindgen]
+impl WebChip8 {
+    /// A machine with one of the `--quirks` profiles, e.g. "default" or "vip"
+    #[wasm_bindgen(constructor)]
+    pub fn new(quirks: &str) -> Result<WebChip8, JsValue> {
+        let quirks = Quirks::from_name(quirks)
+            .ok_or_else(|| JsValue::from_str(&format!("unknown quirks '{}'", quirks)))?;
+        // Without std the cpu has nowhere to get a random seed from
+        let settings = Settings { quirks, seed: Some(js_sys::Math::random().to_bits()), ..Settings::new() };
+        let chip8 = Chip8::new(settings).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(WebChip8 { chip8, beeper: None })
+    }
+
+    /// Load a ROM and start it
+    pub fn load(&mut self, bytes: Vec<u8>) -> Result<(), JsValue> {
+        let rom = Rom::from_bytes(bytes).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.chip8.load(rom).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn reset(&mut self) {
+        self.chip8.reset();
+    }
+
+    /// Run a 60th of a second and turn the beeper on or off
+    pub fn run_frame(&mut self) {
+        self.chip8.run_frame();
+        if let Some(ref beeper) = self.beeper {
+            beeper.set(self.chip8.is_beeping());
+        }
+    }
+
+    /// Start making sound. Browsers only allow it after the user has clicked or pressed a key,
+    /// so call this from an event handler.
+    pub fn enable_audio(&mut self) -> Result<(), JsValue> {
+        if self.beeper.is_none() {
+            self.beeper = Some(Beeper::new()?);
+        }
+        Ok(())
+    }
+
+    /// Press or release the keypad key for `key`, a `KeyboardEvent.key`. False if it isn't one,
+    /// so the page can let the browser handle it.
+    pub fn key(&mut self, key: &str, down: bool) -> bool {
+        let mut chars = key.chars();
+        let keypad_key = match (chars.next(), chars.next()) {
+            (Some(c), None) => KEYS.chars().position(|k| k == c.to_ascii_lowercase()),
+            _ => None
+        };
+        match keypad_key {
+            Some(keypad_key) => {
+                self.chip8.set_key(keypad_key as u8, down);
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.chip8.is_beeping()
+    }
+
+    pub fn width(&self) -> usize {
+        self.chip8.framebuffer().width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.chip8.framebuffer().height()
+    }
+
+    /// The screen as RGBA bytes, a row at a time from the top
+    pub fn pixels(&self) -> Vec<u8> {
+        let display = self.chip8.framebuffer();
+        let mut pixels = Vec::with_capacity(display.width() * display.height() * 4);
+        for y in 0..display.height() {
+            for x in 0..display.width() {
+                pixels.extend_from_slice(if display.pixel(x, y) { &FOREGROUND } else { &BACKGROUND });
+            }
+        }
+        pixels
+    }
+
+    /// Draw the screen at the top left of `context`, one canvas pixel per CHIP-8 pixel.
+    /// Scale the canvas up with CSS (`image-rendering: pixelated`) to make it bigger.
+    pub fn draw(&self, context: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        let pixels = self.pixels();
+        let image = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&pixels), self.width() as u32,
+                                                                self.height() as u32)?;
+        context.put_image_data(&image, 0.0, 0.0)
+    }
+}
@@ -0,0 +1,44 @@
+//! Run under node with `cargo test --target wasm32-unknown-unknown --no-default-features --features wasm`,
+//! which needs `wasm-bindgen-test-runner` from wasm-bindgen-cli (see .cargo/config.toml).
+#![cfg(target_arch = "wasm32")]
+
+extern crate rust_chip8;
+extern crate wasm_bindgen_test;
+
+use rust_chip8::wasm::WebChip8;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+fn draws_a_digit() {
+    let mut chip8 = WebChip8::new("default").unwrap();
+    // LD V0, 0xA / LD F, V0 / DRW V1, V1, 5 / JP 0x206
+    chip8.load(vec![0x60, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06]).unwrap();
+    chip8.run_frame();
+
+    let pixels = chip8.pixels();
+    assert_eq!(pixels.len(), chip8.width() * chip8.height() * 4);
+    // The top of the A is 0xF0, four pixels on then four off
+    assert_eq!(&pixels[..4], &[0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(&pixels[4 * 4..4 * 5], &[0x00, 0x00, 0x00, 0xFF]);
+}
+
+#[wasm_bindgen_test]
+fn keys_and_beeps() {
+    let mut chip8 = WebChip8::new("vip").unwrap();
+    // LD V0, K / LD ST, V0 / JP 0x204
+    chip8.load(vec![0xF0, 0x0A, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+    chip8.run_frame();
+    assert!(!chip8.is_beeping());
+
+    assert!(!chip8.key("Enter", true));
+    assert!(chip8.key("E", true));
+    assert!(chip8.key("e", false));
+    chip8.run_frame();
+    assert!(chip8.is_beeping());
+}
+
+#[wasm_bindgen_test]
+fn bad_input() {
+    assert!(WebChip8::new("nope").is_err());
+    assert!(WebChip8::new("default").unwrap().load(vec![]).is_err());
+}